@@ -10,4 +10,14 @@ load-info-failed = Failed to load chart info
 preset-exists = Preset with the same name already exists
 preset-not-found = Specified preset not found
 
+task-not-found = Task not found
+task-not-finished = Task has not finished yet
+batch-not-found = Batch not found
+
+filename-template-unclosed = Unclosed {"{"} in the filename template
+filename-template-unknown = Unknown placeholder {"{"}{ $name }{"}"} in the filename template
+
+disk-space-insufficient = Not enough free space in { $path }: about { $required } is needed but only { $available } is available
+disk-space-low = Free space in { $path } is running low: about { $required } is needed and { $available } is available
+
 not-valid-rpe = Not a valid RPE directory
@@ -2,6 +2,26 @@
 load-avatar-failed = Failed to load avatar
 load-chart-failed = Failed to load chart
 load-music-failed = Failed to load music
+load-replay-failed = Failed to load replay
 load-sfx-failed = Failed to load SFX `{ $name }`
 run-ffmpeg-failed = Failed to run ffmpeg
 no-hwacc = Hardware acceleration is unsupported!
+
+invalid-config = Some settings are invalid
+invalid-resolution = Width and height must be even numbers between 2 and { $max }
+invalid-fps = Frame rate must be between 1 and { $max }
+invalid-sample-count = Must be a power of 2 no greater than { $max }
+invalid-bitrate = Not a valid bitrate, e.g. 7M or 5000k
+invalid-sub-frames = Must be between 1 and { $max }
+invalid-shutter-angle = Must be between 0 and 360
+invalid-supersampling = Supersampling needs a width and height of at most { $max }
+invalid-gpu-yuv = Needs the width to be a multiple of 8 and the height a multiple of 4
+invalid-judgements = Judgements need a positive total weight
+invalid-timeout = Must be at most { $max } seconds, or 0 to disable
+invalid-positive = Must be a positive number
+invalid-non-negative = Must not be negative
+invalid-file = File not found
+invalid-directory = Not a directory
+invalid-number = Must be a number
+invalid-game-own = `{ $name }` has its own option
+invalid-game-unknown = Unknown game setting
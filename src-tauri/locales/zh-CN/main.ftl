@@ -10,4 +10,14 @@ load-info-failed = 加载谱面信息失败
 preset-exists = 同名配置已存在
 preset-not-found = 指定的配置不存在
 
+task-not-found = 任务不存在
+task-not-finished = 任务尚未结束
+batch-not-found = 批次不存在
+
+filename-template-unclosed = 文件名模板中有未闭合的 {"{"}
+filename-template-unknown = 文件名模板中有未知的占位符 {"{"}{ $name }{"}"}
+
+disk-space-insufficient = { $path } 的剩余空间不足：大约需要 { $required }，但只有 { $available } 可用
+disk-space-low = { $path } 的剩余空间紧张：大约需要 { $required }，可用 { $available }
+
 not-valid-rpe = 不是有效的 RPE 目录
@@ -2,6 +2,26 @@
 load-avatar-failed = 加载头像失败
 load-chart-failed = 加载谱面失败
 load-music-failed = 加载音乐失败
+load-replay-failed = 加载回放失败
 load-sfx-failed = 加载音效 `{ $name }` 失败
 run-ffmpeg-failed = 运行 ffmpeg 失败
 no-hwacc = 不支持硬件加速
+
+invalid-config = 部分设置无效
+invalid-resolution = 宽和高必须是 2 到 { $max } 之间的偶数
+invalid-fps = 帧率必须在 1 到 { $max } 之间
+invalid-sample-count = 必须是不大于 { $max } 的 2 的幂
+invalid-bitrate = 无效的码率，例如 7M 或 5000k
+invalid-sub-frames = 必须在 1 到 { $max } 之间
+invalid-shutter-angle = 必须在 0 到 360 之间
+invalid-supersampling = 超采样要求宽和高不超过 { $max }
+invalid-gpu-yuv = 要求宽度为 8 的倍数、高度为 4 的倍数
+invalid-judgements = 判定权重之和必须为正数
+invalid-timeout = 不能超过 { $max } 秒，填 0 表示不限制
+invalid-positive = 必须是正数
+invalid-non-negative = 不能为负数
+invalid-file = 文件不存在
+invalid-directory = 不是文件夹
+invalid-number = 必须是数字
+invalid-game-own = `{ $name }` 有单独的选项
+invalid-game-unknown = 未知的游戏设置
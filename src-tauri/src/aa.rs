@@ -0,0 +1,193 @@
+use anyhow::{anyhow, ensure, Result};
+use macroquad::{miniquad::gl::*, prelude::*};
+use prpr::core::MSRenderTarget;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum AntiAliasing {
+    None,
+    #[default]
+    Msaa,
+    Fxaa,
+    Ssaa2x,
+    Ssaa4x,
+}
+
+impl AntiAliasing {
+    pub fn scale(self) -> u32 {
+        match self {
+            Self::Ssaa2x => 2,
+            Self::Ssaa4x => 4,
+            _ => 1,
+        }
+    }
+
+    pub fn sample_count(self, sample_count: u32) -> u32 {
+        if self == Self::Msaa {
+            sample_count
+        } else {
+            1
+        }
+    }
+}
+
+pub const VERTEX: &str = r#"#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+
+varying highp vec2 uv;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    uv = texcoord;
+}
+"#;
+
+// Tent filter over a 2S x 2S texel footprint, centered on the S x S block
+// covered by the output pixel. Sampling texel centers with a nearest filter
+// keeps the weights exact.
+const FRAGMENT: &str = r#"
+precision highp float;
+
+varying highp vec2 uv;
+
+uniform sampler2D Texture;
+uniform vec2 texelSize;
+
+void main() {
+    vec4 sum = vec4(0.0);
+    float total = 0.0;
+    for (int y = 0; y < 2 * SCALE; y++) {
+        float dy = float(y - SCALE) + 0.5;
+        float wy = 1.0 - abs(dy) / float(SCALE);
+        for (int x = 0; x < 2 * SCALE; x++) {
+            float dx = float(x - SCALE) + 0.5;
+            float w = wy * (1.0 - abs(dx) / float(SCALE));
+            sum += texture2D(Texture, uv + vec2(dx, dy) * texelSize) * w;
+            total += w;
+        }
+    }
+    gl_FragColor = sum / total;
+}
+"#;
+
+struct Downscale {
+    target: RenderTarget,
+    material: Material,
+}
+
+impl Drop for Downscale {
+    fn drop(&mut self) {
+        self.target.delete();
+    }
+}
+
+/// Render target chain for a single render. Scenes always draw into
+/// [`AATarget::input`], and [`AATarget::resolve`] produces the final frame in
+/// [`AATarget::output`] at the requested resolution.
+pub struct AATarget {
+    mst: MSRenderTarget,
+    downscale: Option<Downscale>,
+    dim: (u32, u32),
+    scale: u32,
+}
+
+impl AATarget {
+    pub fn new(mode: AntiAliasing, dim: (u32, u32), sample_count: u32) -> Result<Self> {
+        let scale = mode.scale();
+        let (w, h) = (dim.0 * scale, dim.1 * scale);
+        let mut max_size = 0;
+        unsafe { glGetIntegerv(GL_MAX_TEXTURE_SIZE, &mut max_size) };
+        ensure!(
+            w.max(h) <= max_size as u32,
+            "{w}x{h} render target exceeds the maximum texture size ({max_size})"
+        );
+        let mst = MSRenderTarget::new((w, h), mode.sample_count(sample_count));
+        let downscale = if scale > 1 {
+            let target = render_target(dim.0, dim.1);
+            target.texture.set_filter(FilterMode::Nearest);
+            mst.output().texture.set_filter(FilterMode::Nearest);
+            let material = load_material(
+                VERTEX,
+                &format!("#version 100\n#define SCALE {scale}\n{FRAGMENT}"),
+                MaterialParams {
+                    uniforms: vec![("texelSize".to_owned(), UniformType::Float2)],
+                    ..Default::default()
+                },
+            )
+            .map_err(|err| anyhow!("failed to load downscale shader: {err:?}"))?;
+            material.set_uniform("texelSize", vec2(1. / w as f32, 1. / h as f32));
+            Some(Downscale { target, material })
+        } else {
+            None
+        };
+        Ok(Self {
+            mst,
+            downscale,
+            dim,
+            scale,
+        })
+    }
+
+    pub fn input_dim(&self) -> (u32, u32) {
+        (self.dim.0 * self.scale, self.dim.1 * self.scale)
+    }
+
+    pub fn input(&self) -> RenderTarget {
+        self.mst.input()
+    }
+
+    pub fn output(&self) -> RenderTarget {
+        match &self.downscale {
+            Some(downscale) => downscale.target,
+            None => self.mst.output(),
+        }
+    }
+
+    pub fn resolve(&self) {
+        self.mst.blit();
+        if let Some(downscale) = &self.downscale {
+            draw_fullscreen(
+                self.mst.output().texture,
+                downscale.target,
+                self.dim,
+                downscale.material,
+            );
+        }
+    }
+}
+
+/// Draws `texture` over the whole of `target` with `material`, keeping the
+/// orientation of the source.
+pub fn draw_fullscreen(
+    texture: Texture2D,
+    target: RenderTarget,
+    dim: (u32, u32),
+    material: Material,
+) {
+    push_camera_state();
+    set_camera(&Camera2D {
+        render_target: Some(target),
+        viewport: Some((0, 0, dim.0 as _, dim.1 as _)),
+        ..Default::default()
+    });
+    gl_use_material(material);
+    draw_texture_ex(
+        texture,
+        -1.,
+        -1.,
+        WHITE,
+        DrawTextureParams {
+            dest_size: Some(vec2(2., 2.)),
+            ..Default::default()
+        },
+    );
+    gl_use_default_material();
+    pop_camera_state();
+    unsafe { get_internal_gl() }.flush();
+}
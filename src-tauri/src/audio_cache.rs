@@ -0,0 +1,116 @@
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+use std::{
+    fs::File,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+use tempfile::NamedTempFile;
+
+/// Total size of the mixed tracks kept around, in bytes; the least recently
+/// used ones go first. Tracks are raw f32 samples, about 20 MB per minute.
+const CAPACITY: u64 = 512 << 20;
+
+/// Everything the mixed audio track depends on. The track stops where the
+/// ending music starts, so that it doesn't depend on the video settings;
+/// it's padded to the video length when read.
+pub struct MixKey<'a> {
+    pub chart: &'a Path,
+    pub info: &'a [u8],
+    pub sfx: &'a [u8],
+    pub volume_music: f32,
+    pub volume_sfx: f32,
+    pub offset: f32,
+    pub sample_rate: u32,
+    /// What the player does, if not autoplay.
+    pub play: &'a [u8],
+}
+
+impl MixKey<'_> {
+    fn digest(&self) -> Result<String> {
+        let mut hasher = Sha256::new();
+        crate::cache::hash_path(&mut hasher, self.chart)?;
+        hasher.update(self.info);
+        hasher.update(self.sfx);
+        hasher.update(self.volume_music.to_le_bytes());
+        hasher.update(self.volume_sfx.to_le_bytes());
+        hasher.update(self.offset.to_le_bytes());
+        hasher.update(self.sample_rate.to_le_bytes());
+        hasher.update(self.play);
+        Ok(hex::encode(hasher.finalize()))
+    }
+}
+
+/// On-disk cache of mixed audio tracks, so that re-rendering a chart with
+/// different video settings skips mixing.
+pub struct AudioCache {
+    dir: PathBuf,
+}
+
+impl AudioCache {
+    pub fn new(dir: PathBuf) -> Result<Self> {
+        std::fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    pub fn path(&self, key: &MixKey, extension: &str) -> Result<PathBuf> {
+        Ok(self.dir.join(format!("{}.{extension}", key.digest()?)))
+    }
+
+    /// Whether `path` is cached, marking it as recently used if so.
+    pub fn hit(&self, path: &Path) -> bool {
+        let Ok(file) = File::options().append(true).open(path) else {
+            return false;
+        };
+        let _ = file.set_modified(SystemTime::now());
+        true
+    }
+
+    /// Writes a new entry through `write` and moves it into place once it is
+    /// complete.
+    pub fn store(&self, path: &Path, write: impl FnOnce(&Path) -> Result<()>) -> Result<()> {
+        let file = NamedTempFile::new_in(&self.dir)?;
+        write(file.path())?;
+        file.persist(path)?;
+        if let Err(err) = self.prune() {
+            eprintln!("Failed to prune the audio cache: {err:?}");
+        }
+        Ok(())
+    }
+
+    fn prune(&self) -> Result<()> {
+        let mut entries = std::fs::read_dir(&self.dir)?
+            .filter_map(|it| {
+                let entry = it.ok()?;
+                let metadata = entry.metadata().ok()?;
+                Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+            })
+            .collect::<Vec<_>>();
+        // Newest first; the most recent entry is kept even if it's too large
+        // on its own, since it's about to be used.
+        entries.sort_by(|a, b| b.0.cmp(&a.0));
+        let mut total = 0;
+        for (index, (_, len, path)) in entries.iter().enumerate() {
+            total += len;
+            if index > 0 && total > CAPACITY {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn read_samples(path: &Path) -> Result<Vec<f32>> {
+    let bytes = std::fs::read(path)?;
+    Ok(bytes
+        .chunks_exact(4)
+        .map(|it| f32::from_le_bytes(it.try_into().unwrap()))
+        .collect())
+}
+
+pub fn write_samples(path: &Path, samples: &[f32]) -> Result<()> {
+    let bytes: Vec<u8> = samples.iter().flat_map(|it| it.to_le_bytes()).collect();
+    std::fs::write(path, bytes)?;
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use crate::aa::{draw_fullscreen, VERTEX};
+use anyhow::{anyhow, bail, Result};
+use macroquad::{
+    miniquad::{gl::*, BlendFactor, BlendState, Equation, PipelineParams},
+    prelude::*,
+};
+use prpr::core::internal_id;
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MotionBlurConfig {
+    pub sub_frames: u32,
+    /// In degrees; 360 means the shutter stays open for the whole frame.
+    pub shutter_angle: f64,
+}
+
+impl MotionBlurConfig {
+    /// Time offsets (relative to the frame time, never positive) at which
+    /// the sub-frames of a frame should be rendered. The last sub-frame is
+    /// always at the frame time itself.
+    pub fn offsets(&self, frame_delta: f64) -> Vec<f64> {
+        let count = self.sub_frames.max(1);
+        let span = frame_delta * self.shutter_angle.clamp(0., 360.) / 360.;
+        (1..=count)
+            .map(|i| span * (i as f64 / count as f64 - 1.))
+            .collect()
+    }
+}
+
+const GL_RGBA32F: GLenum = 0x8814;
+
+const ACCUMULATE: &str = r#"#version 100
+precision highp float;
+
+varying highp vec2 uv;
+
+uniform sampler2D Texture;
+uniform float weight;
+
+void main() {
+    gl_FragColor = vec4(texture2D(Texture, uv).rgb * weight, 0);
+}
+"#;
+
+const RESOLVE: &str = r#"#version 100
+precision highp float;
+
+varying highp vec2 uv;
+
+uniform sampler2D Texture;
+
+void main() {
+    gl_FragColor = vec4(texture2D(Texture, uv).rgb, 1);
+}
+"#;
+
+/// Averages sub-frames into a single frame. Each sub-frame is added with a
+/// fixed weight of `1 / K` into a float target, which is then resolved once
+/// into an 8-bit one. Blending into 8 bits directly would round away most of
+/// what the later sub-frames contribute.
+pub struct MotionBlur {
+    accum: RenderTarget,
+    target: RenderTarget,
+    accumulate: Material,
+    resolve: Material,
+    dim: (u32, u32),
+    weight: f32,
+}
+
+impl MotionBlur {
+    pub fn new(dim: (u32, u32), sub_frames: u32) -> Result<Self> {
+        let accum = render_target(dim.0, dim.1);
+        accum.texture.set_filter(FilterMode::Nearest);
+        // Swap the storage of the color attachment for a float one. The
+        // framebuffer refers to the texture object, so it stays attached.
+        unsafe {
+            glBindTexture(
+                GL_TEXTURE_2D,
+                accum.texture.raw_miniquad_texture_handle().gl_internal_id(),
+            );
+            glTexImage2D(
+                GL_TEXTURE_2D,
+                0,
+                GL_RGBA32F as _,
+                dim.0 as _,
+                dim.1 as _,
+                0,
+                GL_RGBA,
+                GL_FLOAT,
+                std::ptr::null(),
+            );
+            glBindTexture(GL_TEXTURE_2D, 0);
+            glBindFramebuffer(GL_FRAMEBUFFER, internal_id(accum));
+            let status = glCheckFramebufferStatus(GL_FRAMEBUFFER);
+            glBindFramebuffer(GL_FRAMEBUFFER, 0);
+            if status != GL_FRAMEBUFFER_COMPLETE {
+                accum.delete();
+                bail!("float render targets are not supported (status 0x{status:X})");
+            }
+        }
+        let target = render_target(dim.0, dim.1);
+        target.texture.set_filter(FilterMode::Nearest);
+        let accumulate = load_material(
+            VERTEX,
+            ACCUMULATE,
+            MaterialParams {
+                pipeline_params: PipelineParams {
+                    color_blend: Some(BlendState::new(
+                        Equation::Add,
+                        BlendFactor::One,
+                        BlendFactor::One,
+                    )),
+                    alpha_blend: Some(BlendState::new(
+                        Equation::Add,
+                        BlendFactor::Zero,
+                        BlendFactor::One,
+                    )),
+                    ..Default::default()
+                },
+                uniforms: vec![("weight".to_owned(), UniformType::Float1)],
+                ..Default::default()
+            },
+        )
+        .map_err(|err| anyhow!("failed to load motion blur shader: {err:?}"))?;
+        let resolve = load_material(VERTEX, RESOLVE, MaterialParams::default())
+            .map_err(|err| anyhow!("failed to load motion blur shader: {err:?}"))?;
+        Ok(Self {
+            accum,
+            target,
+            accumulate,
+            resolve,
+            dim,
+            weight: 1. / sub_frames.max(1) as f32,
+        })
+    }
+
+    pub fn accumulate(&self, texture: Texture2D, index: u32) {
+        if index == 0 {
+            push_camera_state();
+            set_camera(&Camera2D {
+                render_target: Some(self.accum),
+                viewport: Some((0, 0, self.dim.0 as _, self.dim.1 as _)),
+                ..Default::default()
+            });
+            clear_background(BLACK);
+            pop_camera_state();
+        }
+        self.accumulate.set_uniform("weight", self.weight);
+        draw_fullscreen(texture, self.accum, self.dim, self.accumulate);
+    }
+
+    /// Resolves the accumulated sub-frames into an 8-bit target.
+    pub fn output(&self) -> RenderTarget {
+        draw_fullscreen(self.accum.texture, self.target, self.dim, self.resolve);
+        self.target
+    }
+}
+
+impl Drop for MotionBlur {
+    fn drop(&mut self) {
+        self.accum.delete();
+        self.target.delete();
+    }
+}
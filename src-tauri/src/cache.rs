@@ -0,0 +1,136 @@
+use crate::{common::DATA_DIR, fps::FrameRate, render::RenderParams, verify::verify_output};
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tracing::warn;
+
+/// Bumped whenever the renderer changes in a way that makes old outputs stale.
+const CACHE_VERSION: u32 = 1;
+
+/// Settings that don't change what gets rendered.
+const IGNORED_FIELDS: &[&str] = &["watchdog", "output"];
+
+static INDEX_LOCK: Mutex<()> = Mutex::new(());
+
+#[derive(Serialize, Deserialize, Clone)]
+pub struct CacheEntry {
+    pub output: PathBuf,
+    pub size: u64,
+    pub frames: u64,
+    pub fps: FrameRate,
+}
+
+fn index_path() -> PathBuf {
+    DATA_DIR.get().unwrap().join("render-cache.json")
+}
+
+fn load_index() -> HashMap<String, CacheEntry> {
+    let path = index_path();
+    if !path.exists() {
+        return HashMap::new();
+    }
+    File::open(&path)
+        .map_err(anyhow::Error::from)
+        .and_then(|file| Ok(serde_json::from_reader(BufReader::new(file))?))
+        .unwrap_or_else(|err| {
+            warn!("Failed to load render cache index, starting over: {err:?}");
+            HashMap::new()
+        })
+}
+
+fn save_index(index: &HashMap<String, CacheEntry>) -> Result<()> {
+    let file = File::create(index_path())?;
+    serde_json::to_writer(BufWriter::new(file), index)?;
+    Ok(())
+}
+
+pub fn hash_path(hasher: &mut Sha256, path: &Path) -> Result<()> {
+    if path.is_dir() {
+        let mut entries = std::fs::read_dir(path)?
+            .map(|it| Ok(it?.path()))
+            .collect::<Result<Vec<_>>>()?;
+        entries.sort();
+        for entry in entries {
+            hasher.update(
+                entry
+                    .file_name()
+                    .unwrap_or_default()
+                    .to_string_lossy()
+                    .as_bytes(),
+            );
+            hash_path(hasher, &entry)?;
+        }
+    } else {
+        std::io::copy(&mut File::open(path)?, hasher)?;
+    }
+    Ok(())
+}
+
+/// Hashes the chart contents together with every setting that affects the
+/// rendered video.
+pub fn cache_key(params: &RenderParams) -> Result<String> {
+    let mut hasher = Sha256::new();
+    hasher.update(CACHE_VERSION.to_le_bytes());
+    hash_path(&mut hasher, &params.path)?;
+
+    let mut config = serde_json::to_value(&params.config)?;
+    if let Some(config) = config.as_object_mut() {
+        for field in IGNORED_FIELDS {
+            config.remove(*field);
+        }
+    }
+    hasher.update(serde_json::to_vec(&config)?);
+    hasher.update(serde_json::to_vec(&params.info)?);
+    // These can be edited in place, so their contents are hashed too
+    if let Some(avatar) = params.config.player_avatar() {
+        hash_path(&mut hasher, Path::new(avatar))?;
+    }
+    if let Some(res_pack) = params.config.res_pack_path() {
+        hash_path(&mut hasher, Path::new(res_pack))?;
+    }
+    if let Some(replay) = &params.replay {
+        hash_path(&mut hasher, replay)?;
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Returns the previous output for `key`, if it still exists and passes
+/// verification. Stale entries are dropped.
+pub fn lookup(key: &str) -> Option<CacheEntry> {
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let mut index = load_index();
+    let entry = index.get(key)?.clone();
+    let valid = std::fs::metadata(&entry.output).is_ok_and(|it| it.len() == entry.size)
+        && verify_output(&entry.output, entry.frames, entry.fps).is_ok();
+    if valid {
+        return Some(entry);
+    }
+    index.remove(key);
+    if let Err(err) = save_index(&index) {
+        warn!("Failed to save render cache index: {err:?}");
+    }
+    None
+}
+
+/// Records a verified output.
+pub fn record(key: String, output: &Path, frames: u64, fps: FrameRate) -> Result<()> {
+    let _guard = INDEX_LOCK.lock().unwrap();
+    let mut index = load_index();
+    index.insert(
+        key,
+        CacheEntry {
+            output: output.to_owned(),
+            size: std::fs::metadata(output)?.len(),
+            frames,
+            fps,
+        },
+    );
+    save_index(&index)
+}
@@ -0,0 +1,158 @@
+prpr::tl_file!("render");
+
+use crate::{fps::FrameRate, render::cmd_hidden};
+use anyhow::{bail, ensure, Context, Result};
+use std::{
+    fmt::Write as _,
+    io::Write,
+    path::Path,
+    process::{Child, ChildStdin, Stdio},
+};
+
+pub struct VideoOptions {
+    pub dim: (u32, u32),
+    pub fps: FrameRate,
+    /// Whether frames are already flipped YUV420P (see [`crate::yuv`])
+    /// rather than bottom-up RGBA.
+    pub yuv: bool,
+    pub bitrate: String,
+    pub hardware_accel: bool,
+}
+
+/// Bitrate of the encoded audio track, in bits per second.
+pub const AUDIO_BITRATE: usize = 320_000;
+
+/// Parses bitrates in ffmpeg notation like `7M` or `500k` into bits per
+/// second.
+pub fn parse_bitrate(bitrate: &str) -> Result<u64> {
+    let bitrate = bitrate.trim();
+    let (value, scale) = match bitrate.chars().last() {
+        Some('k' | 'K') => (&bitrate[..bitrate.len() - 1], 1e3),
+        Some('m' | 'M') => (&bitrate[..bitrate.len() - 1], 1e6),
+        Some('g' | 'G') => (&bitrate[..bitrate.len() - 1], 1e9),
+        _ => (bitrate, 1.),
+    };
+    let value: f64 = value
+        .parse()
+        .with_context(|| format!("invalid bitrate `{bitrate}`"))?;
+    if !value.is_finite() || value <= 0. {
+        bail!("invalid bitrate `{bitrate}`");
+    }
+    Ok((value * scale).round() as u64)
+}
+
+/// Receives rendered frames in order, one buffer per frame.
+pub trait FrameSink: Send {
+    fn write_frame(&mut self, frame: &[u8]) -> Result<()>;
+
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+/// An ffmpeg binary together with the list of codecs it supports, probed
+/// once per worker.
+pub struct Ffmpeg {
+    path: String,
+    codecs: String,
+}
+
+impl Ffmpeg {
+    pub fn new(path: String) -> Result<Self> {
+        let codecs = String::from_utf8(
+            cmd_hidden(&path)
+                .arg("-codecs")
+                .output()
+                .with_context(|| tl!("run-ffmpeg-failed"))?
+                .stdout,
+        )?;
+        Ok(Self { path, codecs })
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    fn has_codec(&self, name: &str) -> bool {
+        self.codecs.contains(name)
+    }
+}
+
+/// Encodes through an external ffmpeg process fed over stdin. The audio track
+/// is read from a file of raw interleaved stereo f32 samples and encoded to
+/// AAC in the same pass.
+pub struct FfmpegSink {
+    proc: Child,
+    input: Option<ChildStdin>,
+}
+
+impl FfmpegSink {
+    pub fn new(
+        ffmpeg: &Ffmpeg,
+        options: &VideoOptions,
+        audio: &Path,
+        sample_rate: u32,
+        output: &Path,
+    ) -> Result<Self> {
+        let use_cuda = options.hardware_accel && ffmpeg.has_codec("h264_nvenc");
+        let has_qsv = options.hardware_accel && ffmpeg.has_codec("h264_qsv");
+
+        let (vw, vh) = options.dim;
+        let mut args = "-y -f rawvideo -c:v rawvideo".to_owned();
+        if use_cuda {
+            args += " -hwaccel_output_format cuda";
+        }
+        write!(
+            &mut args,
+            " -s {vw}x{vh} -r {}/{} -pix_fmt {} -i - -f f32le -ar {sample_rate} -ac 2 -i",
+            options.fps.num(),
+            options.fps.den(),
+            if options.yuv { "yuv420p" } else { "rgba" },
+        )?;
+
+        let mut args2 = format!(
+            "-c:a aac -b:a {AUDIO_BITRATE} -c:v {} -pix_fmt yuv420p -b:v {} -map 0:v:0 -map 1:a:0",
+            if use_cuda {
+                "h264_nvenc"
+            } else if has_qsv {
+                "h264_qsv"
+            } else if options.hardware_accel {
+                bail!(tl!("no-hwacc"));
+            } else {
+                // "libx264 -preset ultrafast"
+                "libx264"
+            },
+            options.bitrate,
+        );
+        if options.yuv {
+            args2 += " -color_range tv -colorspace bt709 -color_primaries bt709 -color_trc bt709";
+        } else {
+            args2 += " -vf vflip";
+        }
+        args2 += " -f mp4";
+
+        let mut proc = cmd_hidden(ffmpeg.path())
+            .args(args.split_whitespace())
+            .arg(audio)
+            .args(args2.split_whitespace())
+            .arg(output)
+            .stdin(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()
+            .with_context(|| tl!("run-ffmpeg-failed"))?;
+        let input = proc.stdin.take();
+        Ok(Self { proc, input })
+    }
+}
+
+impl FrameSink for FfmpegSink {
+    fn write_frame(&mut self, frame: &[u8]) -> Result<()> {
+        self.input.as_mut().unwrap().write_all(frame)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        drop(self.input.take());
+        let status = self.proc.wait()?;
+        ensure!(status.success(), "ffmpeg exited abnormally ({status})");
+        Ok(())
+    }
+}
@@ -0,0 +1,151 @@
+use anyhow::{bail, Context, Result};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::{fmt, str::FromStr};
+
+/// Frame rate as an exact fraction, so that NTSC rates like `30000/1001`
+/// do not drift over long charts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FrameRate {
+    num: u32,
+    den: u32,
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+impl FrameRate {
+    pub fn new(num: u32, den: u32) -> Result<Self> {
+        if num == 0 || den == 0 {
+            bail!("invalid frame rate {num}/{den}");
+        }
+        let g = gcd(num as u64, den as u64) as u32;
+        Ok(Self {
+            num: num / g,
+            den: den / g,
+        })
+    }
+
+    /// Decimal rates close to `n * 1000 / 1001` (29.97, 59.94, 23.976...)
+    /// are taken as the NTSC rate they stand for.
+    pub fn from_f64(value: f64) -> Result<Self> {
+        if !value.is_finite() || value <= 0. || value > u32::MAX as f64 / 1001. {
+            bail!("invalid frame rate {value}");
+        }
+        let ntsc = (value * 1.001).round();
+        if (ntsc * 1000. / 1001. - value).abs() < 5e-3 && value.fract() != 0. {
+            return Self::new(ntsc as u32 * 1000, 1001);
+        }
+        Self::new((value * 1000.).round() as u32, 1000)
+    }
+
+    pub fn num(&self) -> u32 {
+        self.num
+    }
+
+    pub fn den(&self) -> u32 {
+        self.den
+    }
+
+    pub fn frame_delta(&self) -> f64 {
+        self.den as f64 / self.num as f64
+    }
+
+    /// Timestamp of the given frame, computed from the index rather than
+    /// accumulated.
+    pub fn frame_time(&self, frame: u64) -> f64 {
+        (frame as u128 * self.den as u128) as f64 / self.num as f64
+    }
+
+    /// Number of frames needed to cover `duration` seconds.
+    pub fn frames_in(&self, duration: f64) -> u64 {
+        (duration * self.num as f64 / self.den as f64).ceil() as u64
+    }
+
+    /// Number of audio frames exactly matching `frames` video frames,
+    /// rounded to the nearest sample.
+    pub fn samples_for(&self, frames: u64, sample_rate: u32) -> u64 {
+        let num = frames as u128 * self.den as u128 * sample_rate as u128;
+        let den = self.num as u128;
+        ((num + den / 2) / den) as u64
+    }
+}
+
+impl Default for FrameRate {
+    fn default() -> Self {
+        Self { num: 60, den: 1 }
+    }
+}
+
+impl fmt::Display for FrameRate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.den == 1 {
+            write!(f, "{}", self.num)
+        } else {
+            write!(f, "{}/{}", self.num, self.den)
+        }
+    }
+}
+
+impl FromStr for FrameRate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        if let Some((num, den)) = s.split_once('/') {
+            let num = num.trim().parse().context("invalid frame rate numerator")?;
+            let den = den
+                .trim()
+                .parse()
+                .context("invalid frame rate denominator")?;
+            return Self::new(num, den);
+        }
+        if let Ok(value) = s.parse::<u32>() {
+            return Self::new(value, 1);
+        }
+        Self::from_f64(s.parse().context("invalid frame rate")?)
+    }
+}
+
+impl Serialize for FrameRate {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for FrameRate {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct Visitor;
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = FrameRate;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a frame rate like 60, 59.94 or \"30000/1001\"")
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                u32::try_from(v)
+                    .map_err(E::custom)
+                    .and_then(|v| FrameRate::new(v, 1).map_err(E::custom))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                u32::try_from(v)
+                    .map_err(E::custom)
+                    .and_then(|v| FrameRate::new(v, 1).map_err(E::custom))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                FrameRate::from_f64(v).map_err(E::custom)
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+        deserializer.deserialize_any(Visitor)
+    }
+}
@@ -0,0 +1,57 @@
+// miniquad always creates its GL context through a native window, so on a
+// machine without X11 or Wayland the render subprocess starts a virtual
+// framebuffer of its own and renders into it with Mesa's software rasterizer
+// (llvmpipe).
+//
+// Xvfb is started directly rather than through xvfb-run, which merges the
+// stderr of the command into its stdout, and stdout is the IPC channel.
+
+#[cfg(target_os = "linux")]
+pub fn ensure_display() {
+    use std::{
+        io::{BufRead, BufReader},
+        process::{Command, Stdio},
+    };
+
+    // Setting it disables the virtual framebuffer, e.g. to use a display
+    // server that is set up some other way.
+    const DISABLE: &str = "PHIRA_RENDER_NO_XVFB";
+
+    let has_display = ["DISPLAY", "WAYLAND_DISPLAY"]
+        .iter()
+        .any(|it| std::env::var_os(it).is_some_and(|it| !it.is_empty()));
+    if has_display || std::env::var_os(DISABLE).is_some() {
+        return;
+    }
+    eprintln!("No display found, rendering with Xvfb and software GL");
+    // Xvfb picks a free display itself and writes its number to the given fd.
+    // With -terminate it exits once the render process disconnects; when the
+    // render is cancelled, it's killed along with the process group.
+    let mut child = match Command::new("Xvfb")
+        .args(["-displayfd", "1", "-terminate", "-nolisten", "tcp"])
+        .args(["-screen", "0", "1280x720x24", "+extension", "GLX"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(err) => {
+            eprintln!("Failed to run Xvfb ({err}), is it installed?");
+            return;
+        }
+    };
+    let mut display = String::new();
+    let read = BufReader::new(child.stdout.take().unwrap()).read_line(&mut display);
+    let display = display.trim();
+    if read.is_err() || display.is_empty() {
+        eprintln!("Xvfb failed to start");
+        let _ = child.kill();
+        return;
+    }
+    std::env::set_var("DISPLAY", format!(":{display}"));
+    std::env::set_var("LIBGL_ALWAYS_SOFTWARE", "1");
+    std::env::set_var("GALLIUM_DRIVER", "llvmpipe");
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn ensure_display() {}
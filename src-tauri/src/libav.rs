@@ -0,0 +1,293 @@
+prpr::tl_file!("render");
+
+use crate::{
+    encoder::{parse_bitrate, FrameSink, VideoOptions, AUDIO_BITRATE},
+    fps::FrameRate,
+};
+use anyhow::{anyhow, bail, Context, Result};
+use ffmpeg_next::{
+    codec, color, encoder, ffi,
+    format::{self, context::Output, sample, Pixel, Sample},
+    frame,
+    software::scaling,
+    ChannelLayout, Packet, Rational,
+};
+use std::path::Path;
+
+struct Stream<E> {
+    encoder: E,
+    index: usize,
+    time_base: Rational,
+}
+
+/// Encodes video and the mixed audio in-process through libav, without an
+/// external ffmpeg binary or a temporary audio file.
+pub struct LibavSink {
+    output: Output,
+    video: Stream<encoder::Video>,
+    audio: Stream<encoder::Audio>,
+    scaler: Option<scaling::Context>,
+    rgba: frame::Video,
+    yuv: frame::Video,
+    dim: (u32, u32),
+
+    samples: Vec<f32>,
+    sample_rate: u32,
+    audio_pos: usize,
+    fps: FrameRate,
+    frame: u64,
+}
+
+// libav contexts are not tied to the thread that created them; the sink is
+// only ever used by one thread at a time.
+unsafe impl Send for LibavSink {}
+
+impl LibavSink {
+    pub fn new(
+        options: &VideoOptions,
+        samples: Vec<f32>,
+        sample_rate: u32,
+        path: &Path,
+    ) -> Result<Self> {
+        ffmpeg_next::init()?;
+        let mut output = format::output_as(&path, "mp4")
+            .with_context(|| format!("failed to create {}", path.display()))?;
+        let global_header = output
+            .format()
+            .flags()
+            .contains(format::Flags::GLOBAL_HEADER);
+
+        let (vw, vh) = options.dim;
+        let codec = if options.hardware_accel {
+            ["h264_nvenc", "h264_qsv"]
+                .into_iter()
+                .find_map(encoder::find_by_name)
+                .ok_or_else(|| anyhow!(tl!("no-hwacc")))?
+        } else {
+            encoder::find_by_name("libx264")
+                .or_else(|| encoder::find(codec::Id::H264))
+                .ok_or_else(|| anyhow!("no H.264 encoder available"))?
+        };
+        let index = output.add_stream(codec)?.index();
+        let mut video = codec::context::Context::new_with_codec(codec)
+            .encoder()
+            .video()?;
+        let time_base = Rational::new(options.fps.den() as i32, options.fps.num() as i32);
+        video.set_width(vw);
+        video.set_height(vh);
+        video.set_format(Pixel::YUV420P);
+        video.set_time_base(time_base);
+        video.set_frame_rate(Some(Rational::new(
+            options.fps.num() as i32,
+            options.fps.den() as i32,
+        )));
+        video.set_bit_rate(parse_bitrate(&options.bitrate)? as usize);
+        video.set_colorspace(color::Space::BT709);
+        video.set_color_range(color::Range::MPEG);
+        // Not exposed by ffmpeg-next, tagged like the ffmpeg path does
+        unsafe {
+            let context = video.as_mut_ptr();
+            (*context).color_primaries = ffi::AVColorPrimaries::AVCOL_PRI_BT709;
+            (*context).color_trc = ffi::AVColorTransferCharacteristic::AVCOL_TRC_BT709;
+        }
+        if global_header {
+            video.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let video = video.open_as(codec)?;
+        output.stream_mut(index).unwrap().set_parameters(&video);
+        let video = Stream {
+            encoder: video,
+            index,
+            time_base,
+        };
+
+        let codec = encoder::find(codec::Id::AAC).ok_or_else(|| anyhow!("no AAC encoder"))?;
+        let index = output.add_stream(codec)?.index();
+        let mut audio = codec::context::Context::new_with_codec(codec)
+            .encoder()
+            .audio()?;
+        let time_base = Rational::new(1, sample_rate as i32);
+        audio.set_rate(sample_rate as i32);
+        audio.set_channel_layout(ChannelLayout::STEREO);
+        audio.set_format(Sample::F32(sample::Type::Planar));
+        audio.set_bit_rate(AUDIO_BITRATE);
+        audio.set_time_base(time_base);
+        if global_header {
+            audio.set_flags(codec::Flags::GLOBAL_HEADER);
+        }
+        let audio = audio.open_as(codec)?;
+        output.stream_mut(index).unwrap().set_parameters(&audio);
+        let audio = Stream {
+            encoder: audio,
+            index,
+            time_base,
+        };
+
+        output.write_header()?;
+
+        let scaler = if options.yuv {
+            None
+        } else {
+            let mut scaler = scaling::Context::get(
+                Pixel::RGBA,
+                vw,
+                vh,
+                Pixel::YUV420P,
+                vw,
+                vh,
+                scaling::Flags::BILINEAR,
+            )?;
+            // swscale defaults to BT.601, while the stream is tagged BT.709
+            // limited range, same as the GPU conversion.
+            let result = unsafe {
+                let coefficients = ffi::sws_getCoefficients(ffi::SWS_CS_ITU709 as _);
+                ffi::sws_setColorspaceDetails(
+                    scaler.as_mut_ptr(),
+                    coefficients,
+                    1,
+                    coefficients,
+                    0,
+                    0,
+                    1 << 16,
+                    1 << 16,
+                )
+            };
+            if result < 0 {
+                bail!("failed to set the colorspace of the scaler");
+            }
+            Some(scaler)
+        };
+
+        Ok(Self {
+            output,
+            video,
+            audio,
+            scaler,
+            rgba: frame::Video::new(Pixel::RGBA, vw, vh),
+            yuv: frame::Video::new(Pixel::YUV420P, vw, vh),
+            dim: options.dim,
+
+            samples,
+            sample_rate,
+            audio_pos: 0,
+            fps: options.fps,
+            frame: 0,
+        })
+    }
+
+    fn drain(
+        output: &mut Output,
+        encoder: &mut encoder::Encoder,
+        index: usize,
+        time_base: Rational,
+    ) -> Result<()> {
+        let dst = output.stream(index).unwrap().time_base();
+        let mut packet = Packet::empty();
+        while encoder.receive_packet(&mut packet).is_ok() {
+            packet.set_stream(index);
+            packet.rescale_ts(time_base, dst);
+            packet.write_interleaved(output)?;
+        }
+        Ok(())
+    }
+
+    /// Encodes audio up to `end` (in stereo frames), keeping it interleaved
+    /// with the video written so far.
+    fn encode_audio(&mut self, end: usize, flush: bool) -> Result<()> {
+        let frame_size = match self.audio.encoder.frame_size() as usize {
+            0 => 1024,
+            size => size,
+        };
+        let total = self.samples.len() / 2;
+        let end = end.min(total);
+        while self.audio_pos + frame_size <= end || (flush && self.audio_pos < end) {
+            let len = frame_size.min(end - self.audio_pos);
+            let mut frame = frame::Audio::new(
+                Sample::F32(sample::Type::Planar),
+                len,
+                ChannelLayout::STEREO,
+            );
+            frame.set_rate(self.sample_rate);
+            frame.set_pts(Some(self.audio_pos as i64));
+            let src = &self.samples[self.audio_pos * 2..(self.audio_pos + len) * 2];
+            for channel in 0..2 {
+                let dst = frame.plane_mut::<f32>(channel);
+                for (dst, src) in dst.iter_mut().zip(src.chunks_exact(2)) {
+                    *dst = src[channel];
+                }
+            }
+            self.audio.encoder.send_frame(&frame)?;
+            Self::drain(
+                &mut self.output,
+                &mut self.audio.encoder,
+                self.audio.index,
+                self.audio.time_base,
+            )?;
+            self.audio_pos += len;
+        }
+        Ok(())
+    }
+
+    fn copy_planes(frame: &mut frame::Video, data: &[u8], dim: (u32, u32), flip: bool) {
+        let mut offset = 0;
+        let planes = if frame.format() == Pixel::RGBA { 1 } else { 3 };
+        for plane in 0..planes {
+            let (row, rows) = match (planes, plane) {
+                (1, _) => (dim.0 as usize * 4, dim.1 as usize),
+                (_, 0) => (dim.0 as usize, dim.1 as usize),
+                _ => (dim.0 as usize / 2, dim.1 as usize / 2),
+            };
+            let stride = frame.stride(plane);
+            let dst = frame.data_mut(plane);
+            for y in 0..rows {
+                let src_y = if flip { rows - 1 - y } else { y };
+                let src = &data[offset + src_y * row..offset + (src_y + 1) * row];
+                dst[y * stride..y * stride + row].copy_from_slice(src);
+            }
+            offset += row * rows;
+        }
+    }
+}
+
+impl FrameSink for LibavSink {
+    fn write_frame(&mut self, data: &[u8]) -> Result<()> {
+        if let Some(scaler) = &mut self.scaler {
+            Self::copy_planes(&mut self.rgba, data, self.dim, true);
+            scaler.run(&self.rgba, &mut self.yuv)?;
+        } else {
+            Self::copy_planes(&mut self.yuv, data, self.dim, false);
+        }
+        self.yuv.set_pts(Some(self.frame as i64));
+        self.video.encoder.send_frame(&self.yuv)?;
+        Self::drain(
+            &mut self.output,
+            &mut self.video.encoder,
+            self.video.index,
+            self.video.time_base,
+        )?;
+        self.frame += 1;
+
+        let end = self.fps.samples_for(self.frame, self.sample_rate) as usize;
+        self.encode_audio(end, false)
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.video.encoder.send_eof()?;
+        Self::drain(
+            &mut self.output,
+            &mut self.video.encoder,
+            self.video.index,
+            self.video.time_base,
+        )?;
+        self.encode_audio(usize::MAX, true)?;
+        self.audio.encoder.send_eof()?;
+        Self::drain(
+            &mut self.output,
+            &mut self.audio.encoder,
+            self.audio.index,
+            self.audio.time_base,
+        )?;
+        self.output.write_trailer()?;
+        Ok(())
+    }
+}
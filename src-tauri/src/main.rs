@@ -3,11 +3,34 @@
 
 prpr::tl_file!("main" mtl);
 
+mod aa;
+mod audio_cache;
+mod blur;
+mod cache;
 mod common;
+mod encoder;
+mod fps;
+mod headless;
 mod ipc;
+#[cfg(feature = "libav")]
+mod libav;
+mod mods;
+mod output;
+mod play;
+mod preflight;
 mod preview;
+mod progress;
+mod readback;
 mod render;
+mod replay;
+mod sim;
 mod task;
+mod validate;
+mod verify;
+mod watchdog;
+mod worker;
+mod writer;
+mod yuv;
 
 use anyhow::{bail, Context, Result};
 use common::{ensure_dir, respack_dir, CONFIG_DIR, DATA_DIR};
@@ -30,12 +53,13 @@ use std::{
     sync::OnceLock,
     time::SystemTime,
 };
-use task::{TaskQueue, TaskView};
+use task::{BatchOutcome, BatchView, PostOutcome, TaskQueue, TaskView};
 use tauri::{
     CustomMenuItem, InvokeError, Manager, State, SystemTray, SystemTrayEvent, SystemTrayMenu,
     SystemTrayMenuItem, WindowEvent,
 };
 use tokio::{io::AsyncWriteExt, process::Command};
+use validate::InvalidConfig;
 
 static ASSET_PATH: OnceLock<PathBuf> = OnceLock::new();
 static LOCK_FILE: OnceLock<tokio::fs::File> = OnceLock::new();
@@ -44,11 +68,18 @@ static LOCK_FILE: OnceLock<tokio::fs::File> = OnceLock::new();
 async fn wrap_async<R>(f: impl Future<Output = Result<R>>) -> Result<R, InvokeError> {
     f.await.map_err(|e| {
         eprintln!("{e:?}");
+        // Passed as is so that the errors can be shown next to the inputs
+        if let Some(err) = e.downcast_ref::<InvalidConfig>() {
+            return InvokeError::from(err);
+        }
         InvokeError::from_anyhow(e)
     })
 }
 
 pub fn build_conf() -> macroquad::window::Conf {
+    if std::env::args().nth(1).as_deref() == Some("render") {
+        headless::ensure_display();
+    }
     macroquad::window::Conf {
         window_title: "Phira".to_string(),
         window_width: 1080,
@@ -97,7 +128,10 @@ async fn main() -> Result<()> {
         .add_item(CustomMenuItem::new("quit".to_owned(), mtl!("tray-quit")));
     let app = tauri::Builder::default()
         .system_tray(SystemTray::new().with_menu(tray_menu))
-        .manage(TaskQueue::new())
+        .setup(|app| {
+            app.manage(TaskQueue::new(app.handle()));
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             is_the_only_instance,
             exit_program,
@@ -107,6 +141,12 @@ async fn main() -> Result<()> {
             post_render,
             get_tasks,
             cancel_task,
+            remove_task,
+            clear_finished_tasks,
+            post_batch,
+            get_batches,
+            cancel_batch,
+            retry_batch,
             get_respacks,
             open_respack_folder,
             get_presets,
@@ -280,6 +320,7 @@ async fn parse_chart(path: &Path) -> Result<ChartInfo, InvokeError> {
 #[tauri::command]
 async fn preview_chart(params: RenderParams) -> Result<(), InvokeError> {
     wrap_async(async move {
+        params.config.validate()?;
         let mut child = Command::new(std::env::current_exe()?)
             .arg("preview")
             .arg(ASSET_PATH.get().unwrap())
@@ -299,12 +340,21 @@ async fn preview_chart(params: RenderParams) -> Result<(), InvokeError> {
 }
 
 #[tauri::command]
-async fn post_render(queue: State<'_, TaskQueue>, params: RenderParams) -> Result<(), InvokeError> {
-    wrap_async(async move {
-        queue.post(params).await?;
-        Ok(())
-    })
-    .await
+async fn post_render(
+    queue: State<'_, TaskQueue>,
+    params: RenderParams,
+    reuse: Option<bool>,
+) -> Result<PostOutcome, InvokeError> {
+    wrap_async(queue.post(params, reuse)).await
+}
+
+#[tauri::command]
+async fn post_batch(
+    queue: State<'_, TaskQueue>,
+    name: String,
+    params: Vec<RenderParams>,
+) -> Result<BatchOutcome, InvokeError> {
+    wrap_async(queue.post_batch(name, params)).await
 }
 
 #[tauri::command]
@@ -314,8 +364,32 @@ async fn get_tasks(queue: State<'_, TaskQueue>) -> Result<Vec<TaskView>, InvokeE
 
 #[tauri::command]
 async fn cancel_task(queue: State<'_, TaskQueue>, id: u32) -> Result<(), InvokeError> {
-    queue.cancel(id).await;
-    Ok(())
+    wrap_async(queue.cancel(id)).await
+}
+
+#[tauri::command]
+async fn remove_task(queue: State<'_, TaskQueue>, id: u32) -> Result<(), InvokeError> {
+    wrap_async(queue.remove(id)).await
+}
+
+#[tauri::command]
+async fn clear_finished_tasks(queue: State<'_, TaskQueue>) -> Result<usize, InvokeError> {
+    Ok(queue.clear_finished().await)
+}
+
+#[tauri::command]
+async fn get_batches(queue: State<'_, TaskQueue>) -> Result<Vec<BatchView>, InvokeError> {
+    Ok(queue.batches().await)
+}
+
+#[tauri::command]
+async fn cancel_batch(queue: State<'_, TaskQueue>, id: u32) -> Result<(), InvokeError> {
+    wrap_async(queue.cancel_batch(id)).await
+}
+
+#[tauri::command]
+async fn retry_batch(queue: State<'_, TaskQueue>, id: u32) -> Result<(), InvokeError> {
+    wrap_async(queue.retry_batch(id)).await
 }
 
 #[derive(Serialize)]
@@ -383,6 +457,7 @@ async fn save_presets(presets: &HashMap<String, RenderConfig>) -> Result<()> {
 async fn add_preset(name: String, config: RenderConfig) -> Result<(), InvokeError> {
     let mut presets = get_presets().await?;
     wrap_async(async move {
+        config.validate()?;
         if presets.insert(name, config).is_some() {
             bail!(mtl!("preset-exists"));
         }
@@ -519,7 +594,8 @@ fn get_rpe_charts() -> Result<Option<Vec<RPEChartInfo>>, InvokeError> {
 
 #[tauri::command]
 fn test_ffmpeg() -> Result<bool, InvokeError> {
-    (|| Ok(find_ffmpeg()?.is_some()))().map_err(InvokeError::from_anyhow)
+    (|| Ok(cfg!(feature = "libav") || find_ffmpeg()?.is_some()))()
+        .map_err(InvokeError::from_anyhow)
 }
 
 #[tauri::command]
@@ -0,0 +1,31 @@
+use prpr::config::Mods;
+use serde::{Deserialize, Serialize};
+
+/// Gameplay mods that can be applied to a render. Autoplay is always on.
+///
+/// Mods that change the playback speed are left out, since the audio track
+/// is mixed separately at normal speed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum Mod {
+    /// Mirrors the chart horizontally.
+    FlipX,
+    /// Notes fade in as they approach the judge line.
+    FadeIn,
+    /// Notes fade out as they approach the judge line.
+    FadeOut,
+}
+
+impl Mod {
+    fn flag(self) -> Mods {
+        match self {
+            Self::FlipX => Mods::FLIP_X,
+            Self::FadeIn => Mods::FADE_IN,
+            Self::FadeOut => Mods::FADE_OUT,
+        }
+    }
+}
+
+pub fn to_mods(mods: &[Mod]) -> Mods {
+    mods.iter().fold(Mods::AUTOPLAY, |acc, it| acc | it.flag())
+}
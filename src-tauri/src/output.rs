@@ -0,0 +1,187 @@
+prpr::tl_file!("main" mtl);
+
+use crate::{common::output_dir, render::RenderParams};
+use anyhow::{bail, Result};
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_TEMPLATE: &str = "{date} {name}";
+
+/// Placeholders that can be used in a filename template.
+const PLACEHOLDERS: &[&str] = &[
+    "name",
+    "level",
+    "charter",
+    "composer",
+    "id",
+    "width",
+    "height",
+    "resolution",
+    "fps",
+    "date",
+];
+
+/// Characters that are not allowed in file names on at least one platform.
+const FORBIDDEN: &[char] = &['<', '>', ':', '"', '/', '\\', '|', '?', '*'];
+
+/// Device names Windows refuses as file names, with or without an extension.
+const RESERVED: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Longest file stem in bytes, leaving room for the collision suffix and the
+/// extension within the usual 255 byte limit.
+const MAX_STEM_LEN: usize = 200;
+
+const EXTENSION: &str = "mp4";
+
+/// Where rendered videos go and how they are named.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct OutputConfig {
+    /// Output directory, or the default one under the data directory.
+    pub dir: Option<PathBuf>,
+    /// File name without extension, with `{placeholder}`s substituted and
+    /// `{{`/`}}` standing for literal braces.
+    pub filename: String,
+}
+
+impl Default for OutputConfig {
+    fn default() -> Self {
+        Self {
+            dir: None,
+            filename: DEFAULT_TEMPLATE.to_owned(),
+        }
+    }
+}
+
+fn placeholder(params: &RenderParams, date: &DateTime<Local>, key: &str) -> Option<String> {
+    let info = &params.info;
+    let (width, height) = params.config.resolution();
+    let fps = params.config.fps();
+    Some(match key {
+        "name" => info.name.clone(),
+        "level" => info.level.clone(),
+        "charter" => info.charter.clone(),
+        "composer" => info.composer.clone(),
+        "id" => info.id.map(|it| it.to_string()).unwrap_or_default(),
+        "width" => width.to_string(),
+        "height" => height.to_string(),
+        "resolution" => format!("{width}x{height}"),
+        "fps" => {
+            if fps.den() == 1 {
+                fps.num().to_string()
+            } else {
+                format!("{:.2}", 1. / fps.frame_delta())
+            }
+        }
+        "date" => date.format("%Y-%m-%d %H-%M-%S").to_string(),
+        _ => return None,
+    })
+}
+
+/// Substitutes the placeholders of `template`.
+pub fn expand(template: &str, mut value: impl FnMut(&str) -> Option<String>) -> Result<String> {
+    let mut result = String::with_capacity(template.len());
+    let mut chars = template.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                let rest = chars.as_str();
+                if let Some(rest) = rest.strip_prefix('{') {
+                    result.push('{');
+                    chars = rest.chars();
+                    continue;
+                }
+                let Some(end) = rest.find('}') else {
+                    bail!(mtl!("filename-template-unclosed"));
+                };
+                let key = &rest[..end];
+                let Some(value) = value(key.trim()) else {
+                    bail!(mtl!("filename-template-unknown", "name" => key));
+                };
+                result.push_str(&value);
+                chars = rest[end + 1..].chars();
+            }
+            '}' => {
+                let rest = chars.as_str();
+                if let Some(rest) = rest.strip_prefix('}') {
+                    chars = rest.chars();
+                }
+                result.push('}');
+            }
+            c => result.push(c),
+        }
+    }
+    Ok(result)
+}
+
+/// Checks that `template` is well-formed and only uses known placeholders.
+pub fn check_template(template: &str) -> Result<()> {
+    expand(template, |key| {
+        PLACEHOLDERS.contains(&key).then(String::new)
+    })
+    .map(drop)
+}
+
+/// Turns `name` into a file name that is valid on every platform.
+pub fn sanitize(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .filter(|it| !it.is_control() && !FORBIDDEN.contains(it))
+        .collect();
+    // Windows silently drops trailing dots and spaces
+    let mut name = name.trim().trim_end_matches(['.', ' ']).to_owned();
+    if name.len() > MAX_STEM_LEN {
+        let mut end = MAX_STEM_LEN;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        name.truncate(end);
+        name = name.trim_end_matches(['.', ' ']).to_owned();
+    }
+    if name.is_empty() {
+        return "untitled".to_owned();
+    }
+    let device = name.split('.').next().unwrap();
+    if RESERVED.iter().any(|it| it.eq_ignore_ascii_case(device)) {
+        name.insert(0, '_');
+    }
+    name
+}
+
+/// Returns `dir/stem.mp4`, or `dir/stem (n).mp4` with the smallest `n` that
+/// neither exists nor is `taken`.
+fn unique_path(dir: &Path, stem: &str, taken: impl Fn(&Path) -> bool) -> PathBuf {
+    let mut n = 1;
+    loop {
+        let name = if n == 1 {
+            format!("{stem}.{EXTENSION}")
+        } else {
+            format!("{stem} ({n}).{EXTENSION}")
+        };
+        let path = dir.join(name);
+        if !path.exists() && !taken(&path) {
+            return path;
+        }
+        n += 1;
+    }
+}
+
+/// Resolves the output file of a render. The directory given in `params`
+/// takes precedence over the one in its config.
+pub fn output_path(params: &RenderParams, taken: impl Fn(&Path) -> bool) -> Result<PathBuf> {
+    let config = params.config.output();
+    let dir = match params.output_dir.as_ref().or(config.dir.as_ref()) {
+        Some(dir) => {
+            std::fs::create_dir_all(dir)?;
+            dir.clone()
+        }
+        None => output_dir()?,
+    };
+    let date = Local::now();
+    let stem = expand(&config.filename, |key| placeholder(params, &date, key))?;
+    Ok(unique_path(&dir, &sanitize(&stem), taken))
+}
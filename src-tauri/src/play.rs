@@ -0,0 +1,56 @@
+// A play fed through prpr's judge in place of autoplay, either simulated (see
+// [`crate::sim`]) or recorded. Events are handed to the game scene in the
+// same form as those of a multiplayer game being watched, so the judge,
+// combo, score and results screen all follow them.
+
+use phira_mp_common::{JudgeEvent, Judgement, TouchFrame};
+use prpr::{
+    core::{Chart, Note},
+    scene::UpdateFn,
+};
+use std::collections::{HashSet, VecDeque};
+
+pub struct Play {
+    /// Touches to show, sorted by time.
+    pub touches: Vec<TouchFrame>,
+    /// Judgements of notes, sorted by time. Holds have one event when their
+    /// head is hit and another once they end.
+    pub judges: Vec<JudgeEvent>,
+}
+
+impl Play {
+    /// Notes that are hit, with the chart time at which their hit sound
+    /// plays. Missed notes have no sound.
+    pub fn hit_sounds<'a>(&'a self, chart: &'a Chart) -> impl Iterator<Item = (f32, &'a Note)> {
+        let mut hit = HashSet::new();
+        self.judges.iter().filter_map(move |event| {
+            if matches!(event.judgement, Judgement::Miss) {
+                return None;
+            }
+            // Only the first event of a hold plays a sound
+            if !hit.insert((event.line_id, event.note_id)) {
+                return None;
+            }
+            let note = chart
+                .lines
+                .get(event.line_id as usize)?
+                .notes
+                .get(event.note_id as usize)?;
+            Some((event.time, note))
+        })
+    }
+
+    /// Hands each event to the game scene once the game time reaches it.
+    pub fn into_update_fn(self) -> UpdateFn {
+        let mut touches = VecDeque::from(self.touches);
+        let mut judges = VecDeque::from(self.judges);
+        Box::new(move |time, touches_out, judges_out| {
+            while touches.front().is_some_and(|it| it.time <= time) {
+                touches_out.push(touches.pop_front().unwrap());
+            }
+            while judges.front().is_some_and(|it| it.time <= time) {
+                judges_out.push(judges.pop_front().unwrap());
+            }
+        })
+    }
+}
@@ -0,0 +1,174 @@
+prpr::tl_file!("main" mtl);
+
+use crate::{
+    common::DATA_DIR,
+    encoder::{parse_bitrate, AUDIO_BITRATE},
+    render::{cmd_hidden, find_ffprobe, video_length, RenderParams, SAMPLE_RATE},
+};
+use anyhow::{bail, Context, Result};
+use prpr::fs;
+use sasa::AudioClip;
+use std::{io::Write, ops::DerefMut, path::Path, process::Stdio};
+use tracing::warn;
+
+/// Free space that is always kept on top of the estimate, which ignores
+/// container overhead and bitrate overshoot.
+const HEADROOM: u64 = 256 << 20;
+
+/// Free space below this multiple of the estimate gets a warning.
+const WARN_FACTOR: u64 = 2;
+
+/// Estimated space a render takes, in bytes.
+pub struct Estimate {
+    /// The video file.
+    pub output: u64,
+    /// The mixed audio track kept in the cache.
+    pub cache: u64,
+    /// The mixed audio track handed to ffmpeg through the temporary folder.
+    pub temp: u64,
+}
+
+/// Reads the duration of `music` with ffprobe, which only has to look at the
+/// headers for most formats.
+fn probe_length(music: &[u8]) -> Result<Option<f64>> {
+    let Some(ffprobe) = find_ffprobe()? else {
+        return Ok(None);
+    };
+    let mut child = cmd_hidden(ffprobe)
+        .args("-v error -show_entries format=duration -of csv=p=0 -i -".split_whitespace())
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()
+        .context("failed to run ffprobe")?;
+    let mut stdin = child.stdin.take().unwrap();
+    let output = std::thread::scope(|scope| {
+        // ffprobe stops reading once it knows the duration, so the broken pipe
+        // that follows is expected
+        scope.spawn(move || {
+            let _ = stdin.write_all(music);
+        });
+        child.wait_with_output()
+    })?;
+    if !output.status.success() {
+        return Ok(None);
+    }
+    Ok(String::from_utf8_lossy(&output.stdout)
+        .trim()
+        .parse::<f64>()
+        .ok()
+        .filter(|it| it.is_finite() && *it > 0.))
+}
+
+pub async fn estimate(params: &RenderParams) -> Result<Estimate> {
+    let mut fs = fs::fs_from_file(&params.path)?;
+    let music = fs.load_file(&params.info.music).await?;
+    let track_length = tokio::task::spawn_blocking(move || -> Result<_> {
+        match probe_length(&music) {
+            Ok(Some(length)) => return Ok(length),
+            Ok(None) => {}
+            Err(err) => warn!("Failed to probe the music length: {err:?}"),
+        }
+        // Decoding takes a while for long songs
+        Ok(AudioClip::new(music)?.length() as f64)
+    })
+    .await??;
+    // The chart offset is only known once the chart is loaded, which is left
+    // to the worker. It only lengthens the video when negative, and by little.
+    let length = video_length(track_length, 0., params.config.ending_length());
+
+    let bytes = |bitrate: u64| (bitrate as f64 * length / 8.).ceil() as u64;
+    // Raw stereo f32 samples
+    let samples = (length * SAMPLE_RATE as f64).ceil() as u64 * 2 * 4;
+    Ok(Estimate {
+        output: bytes(parse_bitrate(params.config.bitrate())?) + bytes(AUDIO_BITRATE as u64),
+        cache: samples,
+        temp: if cfg!(feature = "libav") { 0 } else { samples },
+    })
+}
+
+fn same_volume(a: &Path, b: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        match (a.metadata(), b.metadata()) {
+            (Ok(a), Ok(b)) => a.dev() == b.dev(),
+            _ => false,
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        match (a.canonicalize(), b.canonicalize()) {
+            (Ok(a), Ok(b)) => a.components().next() == b.components().next(),
+            _ => false,
+        }
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    const UNITS: &[&str] = &["B", "KB", "MB", "GB", "TB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024. && unit + 1 < UNITS.len() {
+        size /= 1024.;
+        unit += 1;
+    }
+    format!("{size:.1} {}", UNITS[unit])
+}
+
+/// Adds `bytes` to the space required on the volume of `dir`.
+fn require<'a>(volumes: &mut Vec<(&'a Path, u64)>, dir: &'a Path, bytes: u64) {
+    match volumes.iter_mut().find(|(it, _)| same_volume(it, dir)) {
+        Some((_, required)) => *required += bytes,
+        None => volumes.push((dir, bytes)),
+    }
+}
+
+/// Checks that there is enough free space for rendering each estimate to its
+/// output, one after another. Fails if there clearly isn't, and returns a
+/// warning if it would be tight.
+pub fn check<'a>(
+    renders: impl IntoIterator<Item = (&'a Path, &'a Estimate)>,
+) -> Result<Option<String>> {
+    let cache_dir = DATA_DIR.get().unwrap();
+    let temp_dir = std::env::temp_dir();
+    let mut volumes = Vec::new();
+    for (output, estimate) in renders {
+        require(&mut volumes, output.parent().unwrap(), estimate.output);
+        require(&mut volumes, cache_dir, estimate.cache);
+        require(&mut volumes, &temp_dir, estimate.temp);
+    }
+
+    let mut warning = None;
+    for (dir, required) in volumes {
+        let available = match fs4::available_space(dir) {
+            Ok(available) => available,
+            Err(err) => {
+                warn!(
+                    "Failed to query the free space of {}: {err:?}",
+                    dir.display()
+                );
+                continue;
+            }
+        };
+        if available >= required * WARN_FACTOR + HEADROOM {
+            continue;
+        }
+        let path = dir.display().to_string();
+        let (required_size, available_size) = (format_size(required), format_size(available));
+        if available < required + HEADROOM {
+            bail!(mtl!(
+                "disk-space-insufficient",
+                "path" => path, "required" => required_size, "available" => available_size
+            ));
+        }
+        warning = Some(
+            mtl!(
+                "disk-space-low",
+                "path" => path, "required" => required_size, "available" => available_size
+            )
+            .to_string(),
+        );
+    }
+    Ok(warning)
+}
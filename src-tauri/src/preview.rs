@@ -52,7 +52,7 @@ pub async fn main() -> Result<()> {
 
     let fs = fs::fs_from_file(&params.path)?;
     let info = params.info;
-    let mut config: Config = params.config.to_config();
+    let mut config: Config = params.config.to_config()?;
     config.mods |= Mods::AUTOPLAY;
 
     let font = FontArc::try_from_vec(load_file("font.ttf").await?)?;
@@ -0,0 +1,139 @@
+use crate::watchdog::Phase;
+use serde::Serialize;
+use std::time::Instant;
+
+/// Share of the total work assigned to each phase.
+const WEIGHTS: [(Phase, f64); 4] = [
+    (Phase::Loading, 0.05),
+    (Phase::Mixing, 0.05),
+    (Phase::Rendering, 0.85),
+    (Phase::Finalizing, 0.05),
+];
+
+/// Time constant of the fps moving average, in seconds.
+const FPS_SMOOTHING: f64 = 2.;
+
+fn weight(phase: Phase) -> f64 {
+    WEIGHTS.iter().find(|it| it.0 == phase).unwrap().1
+}
+
+#[derive(Serialize, Clone, Debug)]
+pub struct Progress {
+    /// Progress of the current phase, if it can be measured.
+    pub phase: Option<f64>,
+    /// Weighted progress of the whole task.
+    pub total: f64,
+    /// Estimated seconds until the task finishes, once it can be estimated.
+    pub estimate: Option<f64>,
+}
+
+/// Tracks the phases of a running task and estimates its remaining time from
+/// an exponential moving average of the render speed.
+pub struct ProgressTracker {
+    phase: Phase,
+    phase_start: Instant,
+    frames: u64,
+    rendered: u64,
+    last_frame: Option<Instant>,
+    render_time: f64,
+    fps: Option<f64>,
+}
+
+impl ProgressTracker {
+    pub fn new() -> Self {
+        Self {
+            phase: Phase::Loading,
+            phase_start: Instant::now(),
+            frames: 0,
+            rendered: 0,
+            last_frame: None,
+            render_time: 0.,
+            fps: None,
+        }
+    }
+
+    pub fn phase(&self) -> Phase {
+        self.phase
+    }
+
+    pub fn fps(&self) -> f64 {
+        self.fps.unwrap_or_default()
+    }
+
+    pub fn enter(&mut self, phase: Phase) {
+        if self.phase == Phase::Rendering {
+            self.render_time = self.phase_start.elapsed().as_secs_f64();
+        }
+        self.phase = phase;
+        self.phase_start = Instant::now();
+        if phase == Phase::Rendering {
+            self.last_frame = Some(self.phase_start);
+        }
+    }
+
+    pub fn start_render(&mut self, frames: u64) {
+        self.frames = frames;
+        self.enter(Phase::Rendering);
+    }
+
+    pub fn frame(&mut self) {
+        self.rendered += 1;
+        let now = Instant::now();
+        if let Some(last) = self.last_frame.replace(now) {
+            let dt = now.duration_since(last).as_secs_f64();
+            if dt > 0. {
+                let current = 1. / dt;
+                self.fps = Some(match self.fps {
+                    Some(fps) => fps + (current - fps) * (1. - (-dt / FPS_SMOOTHING).exp()),
+                    None => current,
+                });
+            }
+        }
+        if self.rendered >= self.frames {
+            self.enter(Phase::Finalizing);
+        }
+    }
+
+    fn phase_progress(&self) -> Option<f64> {
+        match self.phase {
+            Phase::Rendering if self.frames > 0 => {
+                Some((self.rendered as f64 / self.frames as f64).min(1.))
+            }
+            _ => None,
+        }
+    }
+
+    /// Expected duration of finalizing, scaled from the time spent rendering.
+    fn finalizing_time(&self, render_time: f64) -> f64 {
+        render_time * weight(Phase::Finalizing) / weight(Phase::Rendering)
+    }
+
+    fn estimate(&self) -> Option<f64> {
+        match self.phase {
+            Phase::Rendering => {
+                let fps = self.fps.filter(|it| *it > 0.)?;
+                let remaining = self.frames.saturating_sub(self.rendered) as f64 / fps;
+                Some(remaining + self.finalizing_time(self.frames as f64 / fps))
+            }
+            Phase::Finalizing => {
+                let elapsed = self.phase_start.elapsed().as_secs_f64();
+                Some((self.finalizing_time(self.render_time) - elapsed).max(0.))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn progress(&self) -> Progress {
+        let phase = self.phase_progress();
+        let done: f64 = WEIGHTS
+            .iter()
+            .take_while(|it| it.0 != self.phase)
+            .map(|it| it.1)
+            .sum();
+        Progress {
+            phase,
+            total: done + weight(self.phase) * phase.unwrap_or_default(),
+            estimate: self.estimate(),
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use anyhow::{bail, Result};
+use macroquad::{miniquad::gl::*, prelude::RenderTarget};
+use prpr::core::internal_id;
+
+const N: usize = 3;
+const GL_READ_ONLY: GLenum = 0x88B8;
+
+fn check_gl(what: &str) -> Result<()> {
+    let err = unsafe { glGetError() };
+    if err != GL_NO_ERROR {
+        bail!("GL error 0x{err:X} during {what}");
+    }
+    Ok(())
+}
+
+/// Asynchronous frame readback through a ring of pixel buffer objects.
+///
+/// Frames are handed to the sink in the order they were read, each exactly
+/// once. The last `N - 1` frames stay in flight until [`PboReader::finish`]
+/// drains them.
+pub struct PboReader {
+    pbos: [GLuint; N],
+    dim: (u32, u32),
+    submitted: u64,
+    written: u64,
+}
+
+impl PboReader {
+    pub fn new(dim: (u32, u32)) -> Result<Self> {
+        let mut pbos: [GLuint; N] = [0; N];
+        unsafe {
+            glGenBuffers(N as _, pbos.as_mut_ptr());
+            for pbo in pbos {
+                glBindBuffer(GL_PIXEL_PACK_BUFFER, pbo);
+                glBufferData(
+                    GL_PIXEL_PACK_BUFFER,
+                    (dim.0 as u64 * dim.1 as u64 * 4) as _,
+                    std::ptr::null(),
+                    GL_STREAM_READ,
+                );
+            }
+            glBindBuffer(GL_PIXEL_PACK_BUFFER, 0);
+        }
+        check_gl("allocating pixel buffers")?;
+        Ok(Self {
+            pbos,
+            dim,
+            submitted: 0,
+            written: 0,
+        })
+    }
+
+    pub fn byte_size(&self) -> usize {
+        self.dim.0 as usize * self.dim.1 as usize * 4
+    }
+
+    pub fn read(
+        &mut self,
+        target: RenderTarget,
+        mut sink: impl FnMut(&[u8]) -> Result<()>,
+    ) -> Result<()> {
+        if self.submitted - self.written == N as u64 {
+            self.drain_one(&mut sink)?;
+        }
+        unsafe {
+            glBindFramebuffer(GL_READ_FRAMEBUFFER, internal_id(target));
+            glBindBuffer(GL_PIXEL_PACK_BUFFER, self.pbos[self.submitted as usize % N]);
+            glReadPixels(
+                0,
+                0,
+                self.dim.0 as _,
+                self.dim.1 as _,
+                GL_RGBA,
+                GL_UNSIGNED_BYTE,
+                std::ptr::null_mut(),
+            );
+            glBindBuffer(GL_PIXEL_PACK_BUFFER, 0);
+        }
+        check_gl("reading pixels")?;
+        self.submitted += 1;
+        Ok(())
+    }
+
+    /// Drains every frame still in flight and returns the total number of
+    /// frames written.
+    pub fn finish(&mut self, mut sink: impl FnMut(&[u8]) -> Result<()>) -> Result<u64> {
+        while self.written < self.submitted {
+            self.drain_one(&mut sink)?;
+        }
+        Ok(self.written)
+    }
+
+    fn drain_one(&mut self, sink: &mut impl FnMut(&[u8]) -> Result<()>) -> Result<()> {
+        let byte_size = self.byte_size();
+        unsafe {
+            glBindBuffer(GL_PIXEL_PACK_BUFFER, self.pbos[self.written as usize % N]);
+            let mut src = glMapBuffer(GL_PIXEL_PACK_BUFFER, GL_READ_ONLY);
+            if src.is_null() {
+                // Mapping may fail transiently while the driver is still
+                // busy with the transfer; wait for it once before giving up.
+                let err = glGetError();
+                glFinish();
+                src = glMapBuffer(GL_PIXEL_PACK_BUFFER, GL_READ_ONLY);
+                if src.is_null() {
+                    glBindBuffer(GL_PIXEL_PACK_BUFFER, 0);
+                    bail!(
+                        "failed to map pixel buffer for frame {} (GL error 0x{err:X})",
+                        self.written
+                    );
+                }
+            }
+            let result = sink(std::slice::from_raw_parts(src as *const u8, byte_size));
+            glUnmapBuffer(GL_PIXEL_PACK_BUFFER);
+            glBindBuffer(GL_PIXEL_PACK_BUFFER, 0);
+            result?;
+        }
+        check_gl("mapping pixel buffer")?;
+        self.written += 1;
+        Ok(())
+    }
+}
+
+impl Drop for PboReader {
+    fn drop(&mut self) {
+        unsafe {
+            glDeleteBuffers(N as _, self.pbos.as_ptr());
+        }
+    }
+}
@@ -1,11 +1,29 @@
 // Prevents additional console window on Windows in release, DO NOT REMOVE!!
 prpr::tl_file!("render");
 
-use anyhow::{bail, Context, Result};
-use macroquad::{miniquad::gl::GLuint, prelude::*};
+#[cfg(not(feature = "libav"))]
+use crate::encoder::Ffmpeg;
+use crate::{
+    aa::{AATarget, AntiAliasing},
+    audio_cache::{read_samples, write_samples, AudioCache, MixKey},
+    blur::{MotionBlur, MotionBlurConfig},
+    encoder::{parse_bitrate, FrameSink, VideoOptions},
+    fps::FrameRate,
+    mods::{to_mods, Mod},
+    output::{check_template, OutputConfig},
+    play::Play,
+    readback::PboReader,
+    sim::{simulate, SimulationConfig},
+    validate::{InvalidConfig, Validator},
+    watchdog::{Phase, WatchdogConfig},
+    writer::FrameWriter,
+    yuv::YuvConverter,
+};
+use anyhow::{ensure, Context, Result};
+use macroquad::prelude::*;
 use prpr::{
     config::{ChallengeModeColor, Config, Mods},
-    core::{internal_id, MSRenderTarget, NoteKind},
+    core::NoteKind,
     fs,
     info::ChartInfo,
     scene::{BasicPlayer, GameMode, GameScene, LoadingScene},
@@ -15,34 +33,85 @@ use prpr::{
 };
 use sasa::AudioClip;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
     cell::RefCell,
-    io::{BufRead, BufWriter, Write},
+    ffi::OsStr,
+    io::BufRead,
     ops::DerefMut,
-    path::PathBuf,
-    process::{Command, Stdio},
+    path::{Path, PathBuf},
+    process::Command,
     rc::Rc,
-    sync::atomic::{AtomicBool, Ordering},
-    time::Instant,
+    time::{Duration, Instant},
 };
-use std::{ffi::OsStr, fmt::Write as _};
-use tempfile::NamedTempFile;
 
-#[derive(Deserialize, Serialize)]
+/// The `antiAliasing` field of [`RenderConfig`]. Configs saved before there
+/// was a choice of modes only have an `fxaa` flag, which is mapped to
+/// [`AntiAliasing::Fxaa`].
+#[derive(Clone, Copy, Default)]
+struct AntiAliasingField(AntiAliasing);
+
+impl<'de> Deserialize<'de> for AntiAliasingField {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Legacy {
+            anti_aliasing: Option<AntiAliasing>,
+            #[serde(default)]
+            fxaa: bool,
+        }
+        let legacy = Legacy::deserialize(deserializer)?;
+        Ok(Self(match legacy.anti_aliasing {
+            Some(anti_aliasing) => anti_aliasing,
+            None if legacy.fxaa => AntiAliasing::Fxaa,
+            None => AntiAliasing::default(),
+        }))
+    }
+}
+
+impl Serialize for AntiAliasingField {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        #[serde(rename_all = "camelCase")]
+        struct Current {
+            anti_aliasing: AntiAliasing,
+        }
+        Current {
+            anti_aliasing: self.0,
+        }
+        .serialize(serializer)
+    }
+}
+
+#[derive(Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RenderConfig {
     resolution: (u32, u32),
     ending_length: f64,
-    fps: u32,
+    fps: FrameRate,
     hardware_accel: bool,
     bitrate: String,
+    #[serde(flatten)]
+    anti_aliasing: AntiAliasingField,
+    #[serde(default)]
+    motion_blur: Option<MotionBlurConfig>,
+    #[serde(default)]
+    gpu_yuv: bool,
+    #[serde(default)]
+    watchdog: WatchdogConfig,
+    #[serde(default)]
+    output: OutputConfig,
+    #[serde(default)]
+    mods: Vec<Mod>,
+    /// Plays as a simulated player rather than autoplay.
+    #[serde(default)]
+    simulation: Option<SimulationConfig>,
 
     aggressive: bool,
     challenge_color: ChallengeModeColor,
     challenge_rank: u32,
     disable_effect: bool,
     double_hint: bool,
-    fxaa: bool,
     note_scale: f32,
     particle: bool,
     player_avatar: Option<String>,
@@ -53,37 +122,305 @@ pub struct RenderConfig {
     speed: f32,
     volume_music: f32,
     volume_sfx: f32,
+    /// Global offset in seconds, as in the game's `conf.yml`.
+    #[serde(default)]
+    offset: f32,
+    #[serde(default)]
+    chart_debug: bool,
+
+    /// Any other prpr [`Config`] fields, under their names in `conf.yml`, so
+    /// that settings added to prpr can be used without changes here.
+    #[serde(default)]
+    game: serde_json::Map<String, serde_json::Value>,
+}
+
+/// prpr [`Config`] fields that are set through their own options rather than
+/// [`RenderConfig::game`], by their Rust names. See [`is_own_game_field`].
+const OWN_GAME_FIELDS: &[&str] = &[
+    "aggressive",
+    "challenge_color",
+    "challenge_rank",
+    "chart_debug",
+    "disable_effect",
+    "double_hint",
+    "fxaa",
+    "mods",
+    "note_scale",
+    "offset",
+    "particle",
+    "player_name",
+    "player_rks",
+    "res_pack_path",
+    "sample_count",
+    "speed",
+    "volume_music",
+    "volume_sfx",
+];
+
+/// Whether `key`, a field name of a serialized [`Config`], is one of
+/// [`OWN_GAME_FIELDS`]. Names are compared ignoring case and underscores, so
+/// that this holds whatever casing `Config` is serialized with.
+fn is_own_game_field(key: &str) -> bool {
+    let normalize = |it: &str| it.replace('_', "").to_ascii_lowercase();
+    let key = normalize(key);
+    OWN_GAME_FIELDS.iter().any(|it| normalize(it) == key)
 }
 
 impl RenderConfig {
-    pub fn to_config(&self) -> Config {
-        Config {
+    pub fn resolution(&self) -> (u32, u32) {
+        self.resolution
+    }
+
+    pub fn fps(&self) -> FrameRate {
+        self.fps
+    }
+
+    pub fn bitrate(&self) -> &str {
+        &self.bitrate
+    }
+
+    pub fn ending_length(&self) -> f64 {
+        self.ending_length
+    }
+
+    pub fn watchdog(&self) -> &WatchdogConfig {
+        &self.watchdog
+    }
+
+    pub fn output(&self) -> &OutputConfig {
+        &self.output
+    }
+
+    pub fn mods(&self) -> &[Mod] {
+        &self.mods
+    }
+
+    pub fn player_avatar(&self) -> Option<&str> {
+        self.player_avatar.as_deref()
+    }
+
+    pub fn res_pack_path(&self) -> Option<&str> {
+        self.res_pack_path.as_deref()
+    }
+
+    pub fn to_config(&self) -> Result<Config> {
+        let game: Config = serde_json::from_value(self.game.clone().into())?;
+        Ok(Config {
             aggressive: self.aggressive,
             challenge_color: self.challenge_color.clone(),
             challenge_rank: self.challenge_rank,
+            chart_debug: self.chart_debug,
             disable_effect: self.disable_effect,
             double_hint: self.double_hint,
-            fxaa: self.fxaa,
+            fxaa: self.anti_aliasing.0 == AntiAliasing::Fxaa,
+            mods: to_mods(&self.mods),
             note_scale: self.note_scale,
+            offset: self.offset,
             particle: self.particle,
             player_name: self.player_name.clone(),
             player_rks: self.player_rks,
-            sample_count: self.sample_count,
+            sample_count: self.anti_aliasing.0.sample_count(self.sample_count),
             res_pack_path: self.res_pack_path.clone(),
             speed: self.speed,
             volume_music: self.volume_music,
             volume_sfx: self.volume_sfx,
-            ..Default::default()
+            ..game
+        })
+    }
+
+    pub fn validate(&self) -> Result<(), InvalidConfig> {
+        const MAX_DIMENSION: u32 = 8192;
+        /// Largest texture GPUs (and llvmpipe) commonly support, which limits
+        /// supersampled renders.
+        const MAX_TEXTURE_SIZE: u32 = 16384;
+        const MAX_FPS: f64 = 480.;
+        const MAX_SAMPLE_COUNT: u32 = 16;
+        const MAX_SUB_FRAMES: u32 = 64;
+        /// A week; anything longer may as well disable the watchdog.
+        const MAX_TIMEOUT: f64 = 7. * 24. * 3600.;
+
+        let non_negative = |it: f64| it.is_finite() && it >= 0.;
+        let positive = |it: f64| it.is_finite() && it > 0.;
+        let exists = |it: &Option<String>| it.as_ref().map_or(true, |it| Path::new(it).exists());
+
+        let mut v = Validator::default();
+        let (w, h) = self.resolution;
+        // yuv420p needs even dimensions
+        v.check(
+            "resolution",
+            [w, h]
+                .iter()
+                .all(|it| (2..=MAX_DIMENSION).contains(it) && it % 2 == 0),
+            || tl!("invalid-resolution", "max" => MAX_DIMENSION).to_string(),
+        );
+        let scale = self.anti_aliasing.0.scale();
+        v.check(
+            "antiAliasing",
+            w.max(h).saturating_mul(scale) <= MAX_TEXTURE_SIZE,
+            || tl!("invalid-supersampling", "max" => MAX_TEXTURE_SIZE / scale).to_string(),
+        );
+        v.check(
+            "gpuYuv",
+            !self.gpu_yuv || YuvConverter::supports((w, h)),
+            || tl!("invalid-gpu-yuv").to_string(),
+        );
+        let fps = 1. / self.fps.frame_delta();
+        v.check("fps", (1. ..=MAX_FPS).contains(&fps), || {
+            tl!("invalid-fps", "max" => MAX_FPS).to_string()
+        });
+        v.check(
+            "sampleCount",
+            self.sample_count.is_power_of_two() && self.sample_count <= MAX_SAMPLE_COUNT,
+            || tl!("invalid-sample-count", "max" => MAX_SAMPLE_COUNT).to_string(),
+        );
+        v.check("bitrate", parse_bitrate(&self.bitrate).is_ok(), || {
+            tl!("invalid-bitrate").to_string()
+        });
+        v.check("endingLength", non_negative(self.ending_length), || {
+            tl!("invalid-non-negative").to_string()
+        });
+        if let Some(blur) = &self.motion_blur {
+            v.check(
+                "motionBlur.subFrames",
+                (1..=MAX_SUB_FRAMES).contains(&blur.sub_frames),
+                || tl!("invalid-sub-frames", "max" => MAX_SUB_FRAMES).to_string(),
+            );
+            v.check(
+                "motionBlur.shutterAngle",
+                (0. ..=360.).contains(&blur.shutter_angle),
+                || tl!("invalid-shutter-angle").to_string(),
+            );
+        }
+        if let Some(sim) = &self.simulation {
+            for (field, weight) in [
+                ("perfect", sim.perfect),
+                ("good", sim.good),
+                ("bad", sim.bad),
+                ("miss", sim.miss),
+                ("spread", sim.spread),
+            ] {
+                v.check(
+                    &format!("simulation.{field}"),
+                    non_negative(weight as f64),
+                    || tl!("invalid-non-negative").to_string(),
+                );
+            }
+            v.check(
+                "simulation.perfect",
+                sim.perfect + sim.good + sim.bad + sim.miss > 0.,
+                || tl!("invalid-judgements").to_string(),
+            );
+        }
+        for phase in Phase::ALL {
+            let secs = self.watchdog.seconds(phase);
+            v.check(
+                &format!("watchdog.{}", phase.field()),
+                secs.is_finite() && secs <= MAX_TIMEOUT,
+                || tl!("invalid-timeout", "max" => MAX_TIMEOUT).to_string(),
+            );
+        }
+        if let Err(err) = check_template(&self.output.filename) {
+            v.check("output.filename", false, || err.to_string());
+        }
+        v.check(
+            "output.dir",
+            self.output
+                .dir
+                .as_ref()
+                .map_or(true, |it| !it.exists() || it.is_dir()),
+            || tl!("invalid-directory").to_string(),
+        );
+
+        v.check("noteScale", positive(self.note_scale as f64), || {
+            tl!("invalid-positive").to_string()
+        });
+        v.check("speed", positive(self.speed as f64), || {
+            tl!("invalid-positive").to_string()
+        });
+        v.check("playerRks", non_negative(self.player_rks as f64), || {
+            tl!("invalid-non-negative").to_string()
+        });
+        v.check(
+            "volumeMusic",
+            non_negative(self.volume_music as f64),
+            || tl!("invalid-non-negative").to_string(),
+        );
+        v.check("volumeSfx", non_negative(self.volume_sfx as f64), || {
+            tl!("invalid-non-negative").to_string()
+        });
+        v.check("playerAvatar", exists(&self.player_avatar), || {
+            tl!("invalid-file").to_string()
+        });
+        v.check("resPackPath", exists(&self.res_pack_path), || {
+            tl!("invalid-file").to_string()
+        });
+        v.check("offset", self.offset.is_finite(), || {
+            tl!("invalid-number").to_string()
+        });
+        let known = serde_json::to_value(Config::default()).unwrap_or_default();
+        for key in self.game.keys() {
+            let field = format!("game.{key}");
+            if known.get(key).is_none() {
+                v.check(&field, false, || tl!("invalid-game-unknown").to_string());
+            } else if is_own_game_field(key) {
+                v.check(&field, false, || {
+                    tl!("invalid-game-own", "name" => key.as_str()).to_string()
+                });
+            }
+        }
+        if let Err(err) = serde_json::from_value::<Config>(self.game.clone().into()) {
+            v.check("game", false, || err.to_string());
         }
+
+        v.finish(|| tl!("invalid-config").to_string())
     }
 }
 
-#[derive(Deserialize, Serialize)]
+#[derive(Clone, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
 pub struct RenderParams {
     pub path: PathBuf,
     pub info: ChartInfo,
     pub config: RenderConfig,
+    /// Overrides the output directory of the config.
+    #[serde(default)]
+    pub output_dir: Option<PathBuf>,
+    /// A recorded play to render instead of autoplay, see [`crate::replay`].
+    /// Takes precedence over a simulated player.
+    #[serde(default)]
+    pub replay: Option<PathBuf>,
+}
+
+pub const SAMPLE_RATE: u32 = 44100;
+
+/// Time from the start of the video to the start of the music.
+const O: f64 = LoadingScene::TOTAL_TIME as f64 + GameScene::BEFORE_TIME as f64;
+/// Time from the end of the music to the ending screen.
+const A: f64 = 0.7 + 0.3 + 0.4;
+
+/// Adds `clip` into `mixed` at `pos` seconds, cut off at the end of `mixed`.
+/// Returns the number of frames added.
+fn place(mixed: &mut [f32], sample_rate: u32, pos: f64, clip: &AudioClip, volume: f32) -> usize {
+    let position = (pos * sample_rate as f64).round() as usize * 2;
+    if position >= mixed.len() {
+        return 0;
+    }
+    let slice = &mut mixed[position..];
+    let len = (slice.len() / 2).min(clip.frame_count());
+    let mut it = slice.iter_mut();
+    // TODO optimize?
+    for frame in clip.frames()[..len].iter() {
+        let dst = it.next().unwrap();
+        *dst += frame.0 * volume;
+        let dst = it.next().unwrap();
+        *dst += frame.1 * volume;
+    }
+    len
+}
+
+/// Length of the video for a track of `track_length` seconds.
+pub fn video_length(track_length: f64, offset: f32, ending_length: f64) -> f64 {
+    O + track_length - offset.min(0.) as f64 + 1. + A + ending_length
 }
 
 #[derive(Serialize, Deserialize)]
@@ -92,6 +429,7 @@ pub enum IPCEvent {
     StartRender(u64),
     Frame,
     Done(f64),
+    Failed(String),
 }
 
 pub async fn build_player(config: &RenderConfig) -> Result<BasicPlayer> {
@@ -115,7 +453,7 @@ pub async fn build_player(config: &RenderConfig) -> Result<BasicPlayer> {
     })
 }
 
-fn cmd_hidden(program: impl AsRef<OsStr>) -> Command {
+pub(crate) fn cmd_hidden(program: impl AsRef<OsStr>) -> Command {
     let cmd = Command::new(program);
     #[cfg(target_os = "windows")]
     {
@@ -128,77 +466,197 @@ fn cmd_hidden(program: impl AsRef<OsStr>) -> Command {
     cmd
 }
 
-pub fn find_ffmpeg() -> Result<Option<String>> {
+fn find_tool(name: &str) -> Result<Option<String>> {
     fn test(path: impl AsRef<OsStr>) -> bool {
         matches!(cmd_hidden(path).arg("-version").output(), Ok(_))
     }
-    if test("ffmpeg") {
-        return Ok(Some("ffmpeg".to_owned()));
+    if test(name) {
+        return Ok(Some(name.to_owned()));
     }
-    eprintln!("Failed to find global ffmpeg. Using bundled ffmpeg");
+    eprintln!("Failed to find global {name}. Using bundled {name}");
     let exe_dir = std::env::current_exe()?.parent().unwrap().to_owned();
-    let ffmpeg = if cfg!(target_os = "windows") {
-        "ffmpeg.exe"
+    let tool = if cfg!(target_os = "windows") {
+        format!("{name}.exe")
     } else {
-        "ffmpeg"
+        name.to_owned()
     };
-    let ffmpeg = exe_dir.join(ffmpeg);
-    Ok(if test(&ffmpeg) {
-        Some(ffmpeg.display().to_string())
+    let tool = exe_dir.join(tool);
+    Ok(if test(&tool) {
+        Some(tool.display().to_string())
     } else {
         None
     })
 }
 
+pub fn find_ffmpeg() -> Result<Option<String>> {
+    find_tool("ffmpeg")
+}
+
+pub fn find_ffprobe() -> Result<Option<String>> {
+    find_tool("ffprobe")
+}
+
+/// Keeps the GL object made for the last job, and reuses it while the jobs
+/// that follow ask for the same key. The objects are not cheap to make, and
+/// not all of them can be freed.
+struct Reused<K, T>(Option<(K, Rc<T>)>);
+
+impl<K, T> Default for Reused<K, T> {
+    fn default() -> Self {
+        Self(None)
+    }
+}
+
+impl<K: PartialEq, T> Reused<K, T> {
+    fn get(&mut self, key: K, create: impl FnOnce() -> Result<T>) -> Result<Rc<T>> {
+        if let Some((old, value)) = &self.0 {
+            if *old == key {
+                return Ok(Rc::clone(value));
+            }
+        }
+        // Free the old one before allocating the new one
+        self.0 = None;
+        let value = Rc::new(create()?);
+        self.0 = Some((key, Rc::clone(&value)));
+        Ok(value)
+    }
+}
+
+/// Assets and probe results that are loaded once and shared by every job a
+/// worker renders.
+struct Shared {
+    painter: TextPainter,
+    #[cfg(not(feature = "libav"))]
+    ffmpeg: Ffmpeg,
+    audio_cache: Option<AudioCache>,
+    ending: AudioClip,
+    sfx_click: AudioClip,
+    sfx_drag: AudioClip,
+    sfx_flick: AudioClip,
+    /// Hash of the sound effects above, part of the audio cache key.
+    sfx_digest: Vec<u8>,
+
+    target: Reused<(AntiAliasing, (u32, u32), u32), AATarget>,
+    blur: Reused<((u32, u32), u32), MotionBlur>,
+    yuv: Reused<(u32, u32), YuvConverter>,
+}
+
+impl Shared {
+    async fn load() -> Result<Self> {
+        let font = FontArc::try_from_vec(load_file("font.ttf").await?)?;
+
+        #[cfg(not(feature = "libav"))]
+        let ffmpeg = {
+            let Some(ffmpeg) = find_ffmpeg()? else {
+                anyhow::bail!("FFmpeg not found")
+            };
+            dbg!(&ffmpeg);
+            Ffmpeg::new(ffmpeg)?
+        };
+
+        let audio_cache = std::env::args()
+            .nth(3)
+            .map(|dir| AudioCache::new(PathBuf::from(dir).join("audio-cache")))
+            .transpose()?;
+
+        let mut sfx_hasher = Sha256::new();
+        macro_rules! ld {
+            ($path:literal) => {{
+                let data = load_file($path).await?;
+                sfx_hasher.update(&data);
+                AudioClip::new(data)
+                    .with_context(|| tl!("load-sfx-failed", "name" => $path))?
+            }};
+        }
+        Ok(Self {
+            painter: TextPainter::new(font, None),
+            #[cfg(not(feature = "libav"))]
+            ffmpeg,
+            audio_cache,
+            ending: ld!("ending.mp3"),
+            sfx_click: ld!("click.ogg"),
+            sfx_drag: ld!("drag.ogg"),
+            sfx_flick: ld!("flick.ogg"),
+            sfx_digest: sfx_hasher.finalize().to_vec(),
+
+            target: Reused::default(),
+            blur: Reused::default(),
+            yuv: Reused::default(),
+        })
+    }
+}
+
+/// Runs a render worker, with the assets directory and optionally a data
+/// directory for caches as arguments. Jobs are read from stdin as a [`RenderParams`] line
+/// followed by an output path line, and each one ends with either
+/// [`IPCEvent::Done`] or [`IPCEvent::Failed`]. The worker exits once stdin is
+/// closed.
 pub async fn main() -> Result<()> {
     use crate::ipc::client::*;
 
     set_pc_assets_folder(&std::env::args().nth(2).unwrap());
 
-    let mut stdin = std::io::stdin().lock();
-    let stdin = &mut stdin;
+    let mut shared = Shared::load().await?;
 
+    let mut stdin = std::io::stdin().lock();
     let mut line = String::new();
-    stdin.read_line(&mut line)?;
-    let params: RenderParams = serde_json::from_str(line.trim())?;
-    let path = params.path;
-
-    line.clear();
-    stdin.read_line(&mut line)?;
-    let output_path: PathBuf = serde_json::from_str(line.trim())?;
+    loop {
+        line.clear();
+        if stdin.read_line(&mut line)? == 0 {
+            break;
+        }
+        let params: RenderParams = serde_json::from_str(line.trim())?;
 
-    let mut fs = fs::fs_from_file(&path)?;
+        line.clear();
+        stdin.read_line(&mut line)?;
+        let output_path: PathBuf = serde_json::from_str(line.trim())?;
 
-    let font = FontArc::try_from_vec(load_file("font.ttf").await?)?;
+        match render(&mut shared, params, &output_path).await {
+            Ok(duration) => send(IPCEvent::Done(duration)),
+            Err(err) => {
+                eprintln!("{err:?}");
+                send(IPCEvent::Failed(format!("{err:?}")));
+            }
+        }
+    }
+    Ok(())
+}
 
-    let Some(ffmpeg) = find_ffmpeg()? else {
-        bail!("FFmpeg not found")
-    };
-    dbg!(&ffmpeg);
+async fn render(shared: &mut Shared, params: RenderParams, output_path: &Path) -> Result<f64> {
+    use crate::ipc::client::*;
 
-    let mut painter = TextPainter::new(font, None);
+    let path = params.path;
+    let mut fs = fs::fs_from_file(&path)?;
 
-    let mut config = params.config.to_config();
-    config.mods = Mods::AUTOPLAY;
+    let mut config = params.config.to_config()?;
 
     let info = params.info;
 
     let (chart, ..) = GameScene::load_chart(fs.deref_mut(), &info)
         .await
         .with_context(|| tl!("load-chart-failed"))?;
-    macro_rules! ld {
-            ($path:literal) => {
-                AudioClip::new(load_file($path).await?)
-                    .with_context(|| tl!("load-sfx-failed", "name" => $path))?
-            };
-        }
+    let play = match &params.replay {
+        Some(replay) => Some(
+            crate::replay::load(replay)
+                .await
+                .with_context(|| tl!("load-replay-failed"))?,
+        ),
+        None => params
+            .config
+            .simulation
+            .as_ref()
+            .map(|it| simulate(it, &chart)),
+    };
+    // Plays are shown the way a multiplayer game being watched is
+    let mode = if play.is_some() {
+        GameMode::View
+    } else {
+        config.mods |= Mods::AUTOPLAY;
+        GameMode::Normal
+    };
     let music: Result<_> = async { AudioClip::new(fs.load_file(&info.music).await?) }.await;
     let music = music.with_context(|| tl!("load-music-failed"))?;
-    let ending = ld!("ending.mp3");
     let track_length = music.length() as f64;
-    let sfx_click = ld!("click.ogg");
-    let sfx_drag = ld!("drag.ogg");
-    let sfx_flick = ld!("flick.ogg");
 
     let mut gl = unsafe { get_internal_gl() };
 
@@ -206,229 +664,251 @@ pub async fn main() -> Result<()> {
     let volume_sfx = std::mem::take(&mut config.volume_sfx);
 
     let length = track_length - chart.offset.min(0.) as f64 + 1.;
-    let video_length = O + length + A + params.config.ending_length;
+    let video_length = video_length(track_length, chart.offset, params.config.ending_length);
+    let fps = params.config.fps;
+    let frames = fps.frames_in(video_length);
+    let video_duration = fps.frame_time(frames);
     let offset = chart.offset.max(0.);
+    // The global offset shifts the notes, and the hit sounds with them
+    let sfx_offset = offset + config.offset;
 
     let render_start_time = Instant::now();
 
     send(IPCEvent::StartMixing);
-    let mixing_output = NamedTempFile::new()?;
-    let sample_rate = 44100;
+    let sample_rate = SAMPLE_RATE;
+    let ending = &shared.ending;
     assert_eq!(sample_rate, ending.sample_rate());
-    assert_eq!(sample_rate, sfx_click.sample_rate());
-    assert_eq!(sample_rate, sfx_drag.sample_rate());
-    assert_eq!(sample_rate, sfx_flick.sample_rate());
-    let mut output = vec![0.0_f32; (video_length * sample_rate as f64).ceil() as usize * 2];
-    {
-        let pos = O - chart.offset.min(0.) as f64;
-        let count = (music.length() as f64 * sample_rate as f64) as usize;
-        let mut it = output[((pos * sample_rate as f64).round() as usize * 2)..].iter_mut();
-        let ratio = 1. / sample_rate as f64;
-        for frame in 0..count {
-            let position = frame as f64 * ratio;
-            let frame = music.sample(position as f32).unwrap_or_default();
-            *it.next().unwrap() += frame.0 * volume_music;
-            *it.next().unwrap() += frame.1 * volume_music;
-        }
-    }
-    let mut place = |pos: f64, clip: &AudioClip, volume: f32| {
-        let position = (pos * sample_rate as f64).round() as usize * 2;
-        if position >= output.len() {
-            return 0;
+    assert_eq!(sample_rate, shared.sfx_click.sample_rate());
+    assert_eq!(sample_rate, shared.sfx_drag.sample_rate());
+    assert_eq!(sample_rate, shared.sfx_flick.sample_rate());
+    let samples = fps.samples_for(frames, sample_rate);
+    let audio_duration = samples as f64 / sample_rate as f64;
+    ensure!(
+        (audio_duration - video_duration).abs() <= 1. / sample_rate as f64,
+        "audio duration ({audio_duration}s) does not match video duration ({video_duration}s)"
+    );
+    // The track up to the ending music, which doesn't depend on the video
+    // settings and is what gets cached.
+    let ending_pos = O + length + A;
+    let mix = || {
+        let mut mixed = vec![0.0_f32; (ending_pos * sample_rate as f64).ceil() as usize * 2];
+        {
+            let pos = O - chart.offset.min(0.) as f64;
+            let count = (music.length() as f64 * sample_rate as f64) as usize;
+            let mut it = mixed[((pos * sample_rate as f64).round() as usize * 2)..].iter_mut();
+            let ratio = 1. / sample_rate as f64;
+            for frame in 0..count {
+                let position = frame as f64 * ratio;
+                let frame = music.sample(position as f32).unwrap_or_default();
+                *it.next().unwrap() += frame.0 * volume_music;
+                *it.next().unwrap() += frame.1 * volume_music;
+            }
         }
-        let slice = &mut output[position..];
-        let len = (slice.len() / 2).min(clip.frame_count());
-        let mut it = slice.iter_mut();
-        // TODO optimize?
-        for frame in clip.frames()[..len].iter() {
-            let dst = it.next().unwrap();
-            *dst += frame.0 * volume;
-            let dst = it.next().unwrap();
-            *dst += frame.1 * volume;
+        let hits: Box<dyn Iterator<Item = _>> = match &play {
+            Some(play) => Box::new(play.hit_sounds(&chart)),
+            None => Box::new(
+                chart
+                    .lines
+                    .iter()
+                    .flat_map(|it| it.notes.iter())
+                    .filter(|it| !it.fake)
+                    .map(|it| (it.time, it)),
+            ),
+        };
+        for (time, note) in hits {
+            place(
+                &mut mixed,
+                sample_rate,
+                O + time as f64 + sfx_offset as f64,
+                match note.kind {
+                    NoteKind::Click | NoteKind::Hold { .. } => &shared.sfx_click,
+                    NoteKind::Drag => &shared.sfx_drag,
+                    NoteKind::Flick => &shared.sfx_flick,
+                },
+                volume_sfx,
+            );
         }
-        return len;
+        mixed
     };
-    for note in chart
-        .lines
-        .iter()
-        .flat_map(|it| it.notes.iter())
-        .filter(|it| !it.fake)
-    {
-        place(
-            O + note.time as f64 + offset as f64,
-            match note.kind {
-                NoteKind::Click | NoteKind::Hold { .. } => &sfx_click,
-                NoteKind::Drag => &sfx_drag,
-                NoteKind::Flick => &sfx_flick,
+    let mix_path = match &shared.audio_cache {
+        Some(cache) => Some(cache.path(
+            &MixKey {
+                chart: &path,
+                info: &serde_json::to_vec(&info)?,
+                sfx: &shared.sfx_digest,
+                volume_music,
+                volume_sfx,
+                offset: chart.offset + config.offset,
+                sample_rate,
+                play: &match &params.replay {
+                    Some(replay) => std::fs::read(replay)?,
+                    None => serde_json::to_vec(&params.config.simulation)?,
+                },
             },
-            volume_sfx,
-        );
-    }
-    let mut pos = O + length + A;
-    while place(pos, &ending, volume_music) != 0 {
+            "f32",
+        )?),
+        None => None,
+    };
+    let mut mixed = match (&shared.audio_cache, &mix_path) {
+        (Some(cache), Some(path)) if cache.hit(path) => read_samples(path)?,
+        (Some(cache), Some(path)) => {
+            let mixed = mix();
+            cache.store(path, |it| write_samples(it, &mixed))?;
+            mixed
+        }
+        _ => mix(),
+    };
+    // Fit the track to the video, and loop the ending music until it ends
+    mixed.resize(samples as usize * 2, 0.);
+    let mut pos = ending_pos;
+    while place(&mut mixed, sample_rate, pos, ending, volume_music) != 0 {
         pos += ending.frame_count() as f64 / sample_rate as f64;
     }
-    let mut proc = cmd_hidden(&ffmpeg)
-        .args("-y -f f32le -ar 44100 -ac 2 -i - -c:a mp3 -f mp3".split_whitespace())
-        .arg(mixing_output.path())
-        .stdin(Stdio::piped())
-        .stderr(Stdio::inherit())
-        .spawn()
-        .with_context(|| tl!("run-ffmpeg-failed"))?;
-    let input = proc.stdin.as_mut().unwrap();
-    let mut writer = BufWriter::new(input);
-    for sample in output.into_iter() {
-        writer.write_all(&sample.to_le_bytes())?;
-    }
-    drop(writer);
-    proc.wait()?;
+    #[cfg(not(feature = "libav"))]
+    let mixing_file = {
+        let file = tempfile::NamedTempFile::new()?;
+        write_samples(file.path(), &mixed)?;
+        file
+    };
 
     let (vw, vh) = params.config.resolution;
-    let mst = Rc::new(MSRenderTarget::new((vw, vh), config.sample_count));
+    let anti_aliasing = params.config.anti_aliasing.0;
+    let sample_count = anti_aliasing.sample_count(params.config.sample_count);
+    let target = shared
+        .target
+        .get((anti_aliasing, (vw, vh), sample_count), || {
+            AATarget::new(anti_aliasing, (vw, vh), sample_count)
+        })?;
+    let (iw, ih) = target.input_dim();
+    let blur = params
+        .config
+        .motion_blur
+        .map(|blur| {
+            shared.blur.get(((vw, vh), blur.sub_frames), || {
+                MotionBlur::new((vw, vh), blur.sub_frames)
+            })
+        })
+        .transpose()?;
     let my_time: Rc<RefCell<f64>> = Rc::new(RefCell::new(0.));
     let tm = TimeManager::manual(Box::new({
         let my_time = Rc::clone(&my_time);
         move || *(*my_time).borrow()
     }));
-    static MSAA: AtomicBool = AtomicBool::new(false);
     let player = build_player(&params.config).await?;
     let mut main = Main::new(
         Box::new(
-            LoadingScene::new(GameMode::Normal, info, config, fs, Some(player), None, None, None).await?,
+            LoadingScene::new(
+                mode,
+                info,
+                config,
+                fs,
+                Some(player),
+                None,
+                play.map(Play::into_update_fn),
+                None,
+            )
+            .await?,
         ),
         tm,
         {
-            let mut cnt = 0;
-            let mst = Rc::clone(&mst);
-            move || {
-                cnt += 1;
-                if cnt == 1 || cnt == 3 {
-                    MSAA.store(true, Ordering::SeqCst);
-                    Some(mst.input())
-                } else {
-                    MSAA.store(false, Ordering::SeqCst);
-                    Some(mst.output())
-                }
-            }
+            let target = Rc::clone(&target);
+            move || Some(target.input())
         },
     )
     .await?;
     main.top_level = false;
-    main.viewport = Some((0, 0, vw as _, vh as _));
-
-    const O: f64 = LoadingScene::TOTAL_TIME as f64 + GameScene::BEFORE_TIME as f64;
-    const A: f64 = 0.7 + 0.3 + 0.4;
-
-    let fps = params.config.fps;
-    let frame_delta = 1. / fps as f32;
-
-    let codecs = String::from_utf8(
-        cmd_hidden(&ffmpeg)
-            .arg("-codecs")
-            .output()
-            .with_context(|| tl!("run-ffmpeg-failed"))?
-            .stdout,
-    )?;
-    let use_cuda = params.config.hardware_accel && codecs.contains("h264_nvenc");
-    let has_qsv = params.config.hardware_accel && codecs.contains("h264_qsv");
-
-    let mut args = "-y -f rawvideo -c:v rawvideo".to_owned();
-    if use_cuda {
-        args += " -hwaccel_output_format cuda";
-    }
-    write!(&mut args, " -s {vw}x{vh} -r {fps} -pix_fmt rgba -i - -i")?;
-
-    let args2 = format!(
-        "-c:a copy -c:v {} -pix_fmt yuv420p -b:v {} -map 0:v:0 -map 1:a:0 -vf vflip -f mp4",
-        if use_cuda {
-            "h264_nvenc"
-        } else if has_qsv {
-            "h264_qsv"
-        } else if params.config.hardware_accel {
-            bail!(tl!("no-hwacc"));
-        } else {
-            // "libx264 -preset ultrafast"
-            "libx264"
-        },
-        params.config.bitrate,
-    );
-
-    let mut proc = cmd_hidden(&ffmpeg)
-        .args(args.split_whitespace())
-        .arg(mixing_output.path())
-        .args(args2.split_whitespace())
-        .arg(output_path)
-        .stdin(Stdio::piped())
-        .stderr(Stdio::inherit())
-        .spawn()
-        .with_context(|| tl!("run-ffmpeg-failed"))?;
-    let mut input = proc.stdin.take().unwrap();
-
-    let byte_size = vw as usize * vh as usize * 4;
-
-    let frames = (video_length / frame_delta as f64).ceil() as u64;
-
-    const N: usize = 3;
-    let mut pbos: [GLuint; N] = [0; N];
-    unsafe {
-        use miniquad::gl::*;
-        glGenBuffers(N as _, pbos.as_mut_ptr());
-        for pbo in pbos {
-            glBindBuffer(GL_PIXEL_PACK_BUFFER, pbo);
-            glBufferData(
-                GL_PIXEL_PACK_BUFFER,
-                (vw as u64 * vh as u64 * 4) as _,
-                std::ptr::null(),
-                GL_STREAM_READ,
-            );
-        }
-        glBindBuffer(GL_PIXEL_PACK_BUFFER, 0);
-    }
+    main.viewport = Some((0, 0, iw as _, ih as _));
+
+    let sub_frame_offsets = params
+        .config
+        .motion_blur
+        .map_or_else(|| vec![0.], |it| it.offsets(fps.frame_delta()));
+
+    let yuv = params
+        .config
+        .gpu_yuv
+        .then(|| shared.yuv.get((vw, vh), || YuvConverter::new((vw, vh))))
+        .transpose()?;
+
+    let options = VideoOptions {
+        dim: (vw, vh),
+        fps,
+        yuv: yuv.is_some(),
+        bitrate: params.config.bitrate.clone(),
+        hardware_accel: params.config.hardware_accel,
+    };
+    #[cfg(feature = "libav")]
+    let sink: Box<dyn FrameSink> = Box::new(crate::libav::LibavSink::new(
+        &options,
+        mixed,
+        sample_rate,
+        output_path,
+    )?);
+    #[cfg(not(feature = "libav"))]
+    let sink: Box<dyn FrameSink> = Box::new(crate::encoder::FfmpegSink::new(
+        &shared.ffmpeg,
+        &options,
+        mixing_file.path(),
+        sample_rate,
+        output_path,
+    )?);
+    let mut encoder = FrameWriter::new(sink, 8);
+
+    let mut reader = PboReader::new(yuv.as_ref().map_or((vw, vh), |it| it.packed_dim()))?;
+    let mut render_time = Duration::ZERO;
+    let mut readback_time = Duration::ZERO;
 
     send(IPCEvent::StartRender(frames));
 
     for frame in 0..frames {
-        *my_time.borrow_mut() = (frame as f32 * frame_delta).max(0.) as f64;
-        gl.quad_gl.render_pass(Some(mst.output().render_pass));
-        clear_background(BLACK);
-        main.viewport = Some((0, 0, vw as _, vh as _));
-        main.update()?;
-        main.render(&mut painter)?;
-        // TODO magic. can't remove this line.
-        draw_rectangle(0., 0., 0., 0., Color::default());
-        gl.flush();
-
-        if MSAA.load(Ordering::SeqCst) {
-            mst.blit();
-        }
-        unsafe {
-            use miniquad::gl::*;
-            let tex = mst.output().texture.raw_miniquad_texture_handle();
-            glBindFramebuffer(GL_READ_FRAMEBUFFER, internal_id(mst.output()));
-
-            glBindBuffer(GL_PIXEL_PACK_BUFFER, pbos[frame as usize % N]);
-            glReadPixels(
-                0,
-                0,
-                tex.width as _,
-                tex.height as _,
-                GL_RGBA,
-                GL_UNSIGNED_BYTE,
-                std::ptr::null_mut(),
-            );
-
-            glBindBuffer(GL_PIXEL_PACK_BUFFER, pbos[(frame + 1) as usize % N]);
-            let src = glMapBuffer(GL_PIXEL_PACK_BUFFER, 0x88B8);
-            if !src.is_null() {
-                input.write_all(&std::slice::from_raw_parts(src as *const u8, byte_size))?;
-                glUnmapBuffer(GL_PIXEL_PACK_BUFFER);
+        let time = fps.frame_time(frame);
+        let start = Instant::now();
+        for (index, offset) in sub_frame_offsets.iter().enumerate() {
+            *my_time.borrow_mut() = (time + offset).max(0.);
+            gl.quad_gl.render_pass(Some(target.input().render_pass));
+            clear_background(BLACK);
+            main.viewport = Some((0, 0, iw as _, ih as _));
+            main.update()?;
+            main.render(&mut shared.painter)?;
+            // TODO magic. can't remove this line.
+            draw_rectangle(0., 0., 0., 0., Color::default());
+            gl.flush();
+
+            target.resolve();
+            if let Some(blur) = &blur {
+                blur.accumulate(target.output().texture, index as u32);
             }
         }
+        let output = blur
+            .as_ref()
+            .map_or_else(|| target.output(), |it| it.output());
+        let output = match &yuv {
+            Some(yuv) => yuv.convert(output),
+            None => output,
+        };
+        render_time += start.elapsed();
+
+        let start = Instant::now();
+        reader.read(output, |data| encoder.write(data))?;
+        readback_time += start.elapsed();
         send(IPCEvent::Frame);
     }
-    drop(input);
-    proc.wait()?;
+    let start = Instant::now();
+    let written = reader.finish(|data| encoder.write(data))?;
+    readback_time += start.elapsed();
+    ensure!(
+        written == frames,
+        "only {written} of {frames} frames were written"
+    );
+    let backpressure = encoder.backpressure();
+    let encode_time = encoder.finish()?;
+    eprintln!(
+        "Render: {:.2}s, readback: {:.2}s, encode backpressure: {:.2}s, encoder write: {:.2}s",
+        render_time.as_secs_f64(),
+        readback_time.saturating_sub(backpressure).as_secs_f64(),
+        backpressure.as_secs_f64(),
+        encode_time.as_secs_f64(),
+    );
 
-    send(IPCEvent::Done(render_start_time.elapsed().as_secs_f64()));
-    Ok(())
+    Ok(render_start_time.elapsed().as_secs_f64())
 }
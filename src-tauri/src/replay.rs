@@ -0,0 +1,92 @@
+// Recorded plays, rendered with their exact touches and judgements instead of
+// autoplay. A replay is a JSON file with the touches and judgements of the
+// play, each timed in seconds of chart time:
+//
+//     {
+//       "touches": [{ "time": 1.5, "points": [[0, 0.12, -0.3]] }],
+//       "judges": [{ "time": 1.52, "lineId": 0, "noteId": 3, "judgement": "perfect" }]
+//     }
+//
+// Touch points are a finger id and a position in the game's coordinates.
+// Holds are judged twice, with `holdPerfect` or `holdGood` when their head is
+// hit and again once they end.
+
+use crate::play::Play;
+use anyhow::Result;
+use phira_mp_common::{CompactPos, JudgeEvent, Judgement, TouchFrame};
+use serde::Deserialize;
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct Replay {
+    touches: Vec<Touches>,
+    judges: Vec<Judge>,
+}
+
+#[derive(Deserialize)]
+struct Touches {
+    time: f32,
+    points: Vec<(i8, f32, f32)>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Judge {
+    time: f32,
+    line_id: u32,
+    note_id: u32,
+    judgement: JudgementName,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+enum JudgementName {
+    Perfect,
+    Good,
+    Bad,
+    Miss,
+    HoldPerfect,
+    HoldGood,
+}
+
+impl From<JudgementName> for Judgement {
+    fn from(value: JudgementName) -> Self {
+        match value {
+            JudgementName::Perfect => Self::Perfect,
+            JudgementName::Good => Self::Good,
+            JudgementName::Bad => Self::Bad,
+            JudgementName::Miss => Self::Miss,
+            JudgementName::HoldPerfect => Self::HoldPerfect,
+            JudgementName::HoldGood => Self::HoldGood,
+        }
+    }
+}
+
+pub async fn load(path: &Path) -> Result<Play> {
+    let replay: Replay = serde_json::from_slice(&tokio::fs::read(path).await?)?;
+    let mut touches = replay
+        .touches
+        .into_iter()
+        .map(|it| TouchFrame {
+            time: it.time,
+            points: it
+                .points
+                .into_iter()
+                .map(|(id, x, y)| (id, CompactPos::new(x, y)))
+                .collect(),
+        })
+        .collect::<Vec<_>>();
+    let mut judges = replay
+        .judges
+        .into_iter()
+        .map(|it| JudgeEvent {
+            time: it.time,
+            line_id: it.line_id,
+            note_id: it.note_id,
+            judgement: it.judgement.into(),
+        })
+        .collect::<Vec<_>>();
+    touches.sort_by(|a, b| a.time.total_cmp(&b.time));
+    judges.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Ok(Play { touches, judges })
+}
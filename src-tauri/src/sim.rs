@@ -0,0 +1,156 @@
+// A simulated player, so that renders don't have to be all perfect. Each note
+// gets a judgement drawn from a configurable mix, and a hit time that falls
+// in that judgement's window. Everything is drawn from a seeded generator, in
+// note order, so the same seed always plays a chart the same way.
+
+use crate::play::Play;
+use phira_mp_common::{JudgeEvent, Judgement};
+use prpr::{
+    core::{Chart, NoteKind},
+    judge::{LIMIT_BAD, LIMIT_GOOD, LIMIT_PERFECT},
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SimulationConfig {
+    pub seed: u32,
+    /// Relative weights of each judgement. Drags and flicks are either
+    /// perfect or missed, and holds can't be bad, so for those the weights
+    /// they can't get go to the next better judgement.
+    pub perfect: f32,
+    pub good: f32,
+    pub bad: f32,
+    pub miss: f32,
+    /// Standard deviation of the timing of perfect hits, in seconds.
+    pub spread: f32,
+}
+
+/// SplitMix64, which is plenty for picking judgements and keeps the output
+/// stable across dependency updates.
+struct Rng(u64);
+
+impl Rng {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `[0, 1)`.
+    fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    fn sign(&mut self) -> f32 {
+        if self.next_u64() & 1 == 0 {
+            -1.
+        } else {
+            1.
+        }
+    }
+
+    /// Normally distributed, through the Box-Muller transform.
+    fn normal(&mut self, std_dev: f32) -> f32 {
+        let u = 1. - self.next_f32();
+        let v = self.next_f32();
+        (-2. * u.ln()).sqrt() * (std::f32::consts::TAU * v).cos() * std_dev
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Outcome {
+    Perfect,
+    Good,
+    Bad,
+    Miss,
+}
+
+impl SimulationConfig {
+    fn outcome(&self, rng: &mut Rng, kind: &NoteKind) -> Outcome {
+        let total = self.perfect + self.good + self.bad + self.miss;
+        let mut pick = rng.next_f32() * total;
+        let mut outcome = Outcome::Miss;
+        for (weight, it) in [
+            (self.perfect, Outcome::Perfect),
+            (self.good, Outcome::Good),
+            (self.bad, Outcome::Bad),
+        ] {
+            if pick < weight {
+                outcome = it;
+                break;
+            }
+            pick -= weight;
+        }
+        match (kind, outcome) {
+            (NoteKind::Drag | NoteKind::Flick, Outcome::Good | Outcome::Bad) => Outcome::Perfect,
+            (NoteKind::Hold { .. }, Outcome::Bad) => Outcome::Good,
+            _ => outcome,
+        }
+    }
+
+    /// Offset of the hit from the note time, in seconds.
+    fn offset(&self, rng: &mut Rng, outcome: Outcome) -> f32 {
+        let between =
+            |rng: &mut Rng, low: f32, high: f32| rng.sign() * (low + (high - low) * rng.next_f32());
+        match outcome {
+            Outcome::Perfect => {
+                let limit = LIMIT_PERFECT * 0.9;
+                rng.normal(self.spread).clamp(-limit, limit)
+            }
+            Outcome::Good => between(rng, LIMIT_PERFECT, LIMIT_GOOD),
+            Outcome::Bad => between(rng, LIMIT_GOOD, LIMIT_BAD),
+            Outcome::Miss => LIMIT_BAD,
+        }
+    }
+}
+
+pub fn simulate(config: &SimulationConfig, chart: &Chart) -> Play {
+    let mut rng = Rng(config.seed as u64);
+    let mut judges = Vec::new();
+    for (line_id, line) in chart.lines.iter().enumerate() {
+        for (note_id, note) in line.notes.iter().enumerate() {
+            if note.fake {
+                continue;
+            }
+            let outcome = config.outcome(&mut rng, &note.kind);
+            // Drags and flicks are judged when the note arrives, however
+            // early the finger is there
+            let offset = match note.kind {
+                NoteKind::Drag | NoteKind::Flick if outcome != Outcome::Miss => 0.,
+                _ => config.offset(&mut rng, outcome),
+            };
+            let mut event = |time: f32, judgement| {
+                judges.push(JudgeEvent {
+                    time,
+                    line_id: line_id as u32,
+                    note_id: note_id as u32,
+                    judgement,
+                })
+            };
+            let time = note.time + offset;
+            match (&note.kind, outcome) {
+                (_, Outcome::Miss) => event(time, Judgement::Miss),
+                (NoteKind::Hold { end_time, .. }, outcome) => {
+                    let (head, end) = if outcome == Outcome::Perfect {
+                        (Judgement::HoldPerfect, Judgement::Perfect)
+                    } else {
+                        (Judgement::HoldGood, Judgement::Good)
+                    };
+                    event(time, head);
+                    event(end_time.max(time), end);
+                }
+                (_, Outcome::Perfect) => event(time, Judgement::Perfect),
+                (_, Outcome::Good) => event(time, Judgement::Good),
+                (_, Outcome::Bad) => event(time, Judgement::Bad),
+            }
+        }
+    }
+    judges.sort_by(|a, b| a.time.total_cmp(&b.time));
+    Play {
+        touches: Vec::new(),
+        judges,
+    }
+}
@@ -1,191 +1,273 @@
+prpr::tl_file!("main" mtl);
+
 use crate::{
-    common::output_dir,
+    cache,
+    mods::Mod,
+    output, preflight,
+    progress::{Progress, ProgressTracker},
     render::{IPCEvent, RenderParams},
-    ASSET_PATH,
+    verify::verify_output,
+    watchdog::Phase,
+    worker::Worker,
 };
-use anyhow::Result;
-use chrono::Local;
+use anyhow::{anyhow, bail, Context, Result};
 use prpr::fs;
 use serde::Serialize;
 use std::{
-    collections::VecDeque,
     io::Write,
     ops::DerefMut,
     path::PathBuf,
-    process::Stdio,
     sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc, Weak,
     },
-    time::Instant,
+    time::{Duration, Instant},
 };
+use tauri::{AppHandle, Manager};
 use tempfile::NamedTempFile;
 use tokio::{
-    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
     sync::{mpsc, Mutex},
     task::JoinHandle,
 };
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 #[derive(Serialize, Clone)]
 #[serde(rename_all = "snake_case", tag = "type")]
 pub enum TaskStatus {
     Pending,
-    Loading,
-    Mixing,
-    Rendering {
-        progress: f64,
-        fps: u64,
-        estimate: f64,
-    },
-    Done {
-        duration: f64,
-        output: String,
-    },
+    Loading { progress: Progress },
+    Mixing { progress: Progress },
+    Rendering { progress: Progress, fps: f64 },
+    Finalizing { progress: Progress },
+    Done { duration: f64, output: String },
     Canceled,
-    Failed {
-        error: String,
-    },
+    Failed { error: String },
+}
+
+impl TaskStatus {
+    pub fn is_finished(&self) -> bool {
+        matches!(
+            self,
+            Self::Done { .. } | Self::Canceled | Self::Failed { .. }
+        )
+    }
 }
 
+#[derive(Serialize, Clone)]
+struct TaskStatusEvent {
+    id: u32,
+    status: TaskStatus,
+}
+
+const PROGRESS_EVENT_INTERVAL: Duration = Duration::from_millis(100);
+
 pub struct Task {
+    app: AppHandle,
     id: u32,
+    batch: Option<Weak<Batch>>,
     name: String,
     cover: NamedTempFile,
     output: PathBuf,
+    cache_key: Option<String>,
 
     params: RenderParams,
     status: Mutex<TaskStatus>,
+    last_progress_event: std::sync::Mutex<Option<Instant>>,
     request_cancel: AtomicBool,
 }
 
 impl Task {
-    async fn new(id: u32, params: RenderParams) -> Result<Self> {
+    async fn new(
+        app: AppHandle,
+        id: u32,
+        batch: Option<Weak<Batch>>,
+        params: RenderParams,
+    ) -> Result<Self> {
         let mut fs = fs::fs_from_file(&params.path)?;
         let info = fs::load_info(fs.deref_mut()).await?;
         let mut cover = NamedTempFile::new()?;
         cover.write_all(&fs.load_file(&info.illustration).await?)?;
 
-        let safe_name: String = info
-            .name
-            .chars()
-            .filter(|&it| it == '-' || it == '_' || it == ' ' || it.is_alphanumeric())
-            .collect();
-        let output = output_dir()?.join(format!(
-            "{} {safe_name}.mp4",
-            Local::now().format("%Y-%m-%d %H-%M-%S")
-        ));
-
         Ok(Self {
+            app,
             id,
+            batch,
             name: info.name,
             cover,
-            output,
+            // Picked when the task is queued, see `TaskQueue::assign_output`
+            output: PathBuf::new(),
+            cache_key: None,
 
             params,
             status: Mutex::new(TaskStatus::Pending),
+            last_progress_event: std::sync::Mutex::default(),
             request_cancel: AtomicBool::default(),
         })
     }
 
-    pub async fn run(&self) -> Result<()> {
+    /// Updates the status and notifies the frontend with a `task-status`
+    /// event. Progress updates within the same status are throttled.
+    async fn set_status(&self, status: TaskStatus) {
+        let mut guard = self.status.lock().await;
+        let transition = std::mem::discriminant(&*guard) != std::mem::discriminant(&status);
+        *guard = status;
+        {
+            let mut last = self.last_progress_event.lock().unwrap();
+            if !transition && last.is_some_and(|it| it.elapsed() < PROGRESS_EVENT_INTERVAL) {
+                return;
+            }
+            *last = Some(Instant::now());
+        }
+        self.emit_status(guard.clone());
+    }
+
+    fn emit_status(&self, status: TaskStatus) {
+        let event = TaskStatusEvent {
+            id: self.id,
+            status,
+        };
+        if let Err(err) = self.app.emit_all("task-status", event) {
+            warn!("Failed to emit status of task #{}: {err:?}", self.id);
+        }
+    }
+
+    pub async fn run(&self, worker: &mut Option<Worker>) -> Result<()> {
+        if self.request_cancel.load(Ordering::Relaxed) {
+            self.set_status(TaskStatus::Canceled).await;
+            return Ok(());
+        }
         info!("Task #{} started ({})", self.id, self.params.path.display());
 
-        *self.status.lock().await = TaskStatus::Loading;
-
-        let mut child = tokio::process::Command::new(std::env::current_exe()?)
-            .arg("render")
-            .arg(ASSET_PATH.get().unwrap())
-            .stdin(Stdio::piped())
-            .stdout(Stdio::piped())
-            .stderr(Stdio::piped())
-            .spawn()?;
-        let mut stdin = child.stdin.take().unwrap();
-        let stdout = child.stdout.take().unwrap();
-
-        stdin
-            .write_all(format!("{}\n", serde_json::to_string(&self.params)?).as_bytes())
-            .await?;
-        stdin
-            .write_all(format!("{}\n", serde_json::to_string(&self.output)?).as_bytes())
-            .await?;
-        stdin.flush().await?;
-
-        let mut lines = BufReader::new(stdout).lines();
+        let mut tracker = ProgressTracker::new();
+        self.set_status(TaskStatus::Loading {
+            progress: tracker.progress(),
+        })
+        .await;
+
+        if !worker.as_mut().is_some_and(Worker::is_alive) {
+            *worker = Some(Worker::spawn()?);
+        }
+        let proc = worker.as_mut().unwrap();
+        proc.submit(&self.params, &self.output).await?;
+
         let mut total = 0;
-        let mut frame_count: u64 = 0;
-        let start = Instant::now();
-        let mut frame_times = VecDeque::new();
-        let mut last_update_fps_sec: u32 = 0;
-        let mut last_fps: usize = 0;
+        let watchdog = *self.params.config.watchdog();
         loop {
-            let line = lines.next_line().await?;
-            let Some(line) = line else { break };
-            let Ok(event): Result<IPCEvent, _> = serde_json::from_str(line.trim()) else { continue };
+            let phase = tracker.phase();
+            let timeout = watchdog.timeout(phase);
+            let Ok(event) = tokio::time::timeout(timeout, proc.next_event()).await else {
+                warn!("Task #{} timed out while {}", self.id, phase.name());
+                proc.kill_tree().await?;
+                let log = proc.take_log().await;
+                *worker = None;
+                self.set_status(TaskStatus::Failed {
+                    error: format!(
+                        "Render timed out: no progress for {}s while {}\n\n{log}",
+                        timeout.as_secs_f64(),
+                        phase.name()
+                    ),
+                })
+                .await;
+                return Ok(());
+            };
+            let Some(event) = event? else { break };
             match event {
                 IPCEvent::StartMixing => {
-                    *self.status.lock().await = TaskStatus::Mixing;
+                    tracker.enter(Phase::Mixing);
+                    self.set_status(TaskStatus::Mixing {
+                        progress: tracker.progress(),
+                    })
+                    .await;
                 }
                 IPCEvent::StartRender(total_frame) => {
-                    *self.status.lock().await = TaskStatus::Rendering {
-                        progress: 0.,
-                        fps: 0,
-                        estimate: 0.,
-                    };
                     total = total_frame;
+                    tracker.start_render(total);
+                    self.set_status(TaskStatus::Rendering {
+                        progress: tracker.progress(),
+                        fps: 0.,
+                    })
+                    .await;
                 }
                 IPCEvent::Frame => {
-                    frame_count += 1;
-                    let cur = start.elapsed().as_secs_f64();
-                    let sec = cur as u32;
-                    frame_times.push_back(cur);
-                    while frame_times.front().is_some_and(|it| cur - *it > 1.) {
-                        frame_times.pop_front();
-                    }
-                    if last_update_fps_sec != sec {
-                        last_fps = frame_times.len();
-                        last_update_fps_sec = sec;
-                    }
-                    let estimate =
-                        total.saturating_sub(frame_count).max(1) as f64 / last_fps as f64;
-                    *self.status.lock().await = TaskStatus::Rendering {
-                        progress: frame_count as f64 / total as f64,
-                        fps: last_fps as u64,
-                        estimate,
-                    };
+                    tracker.frame();
+                    let progress = tracker.progress();
+                    self.set_status(if tracker.phase() == Phase::Finalizing {
+                        TaskStatus::Finalizing { progress }
+                    } else {
+                        TaskStatus::Rendering {
+                            progress,
+                            fps: tracker.fps(),
+                        }
+                    })
+                    .await;
                 }
                 IPCEvent::Done(duration) => {
-                    let output = child.wait_with_output().await?;
-                    let stdout = String::from_utf8(output.stdout)
-                        .unwrap_or_else(|_| "Invalid output".to_owned());
-                    let stderr = String::from_utf8(output.stderr)
-                        .unwrap_or_else(|_| "Invalid output".to_owned());
-                    *self.status.lock().await = TaskStatus::Done {
-                        duration,
-                        output: format!("[STDOUT]\n{stdout}\n\n[STDERR]\n{stderr}"),
+                    let log = proc.take_log().await;
+                    let path = self.output.clone();
+                    let fps = self.params.config.fps();
+                    let verified =
+                        tokio::task::spawn_blocking(move || verify_output(&path, total, fps))
+                            .await?;
+                    let log = match verified {
+                        Ok(None) => log,
+                        Ok(Some(note)) => format!("{note}\n\n{log}"),
+                        Err(err) => {
+                            self.set_status(TaskStatus::Failed {
+                                error: format!("Output verification failed: {err:#}\n\n{log}"),
+                            })
+                            .await;
+                            return Ok(());
+                        }
                     };
+                    if let Some(key) = self.cache_key.clone() {
+                        let path = self.output.clone();
+                        let recorded = tokio::task::spawn_blocking(move || {
+                            cache::record(key, &path, total, fps)
+                        })
+                        .await?;
+                        if let Err(err) = recorded {
+                            warn!(
+                                "Failed to record task #{} in the render cache: {err:?}",
+                                self.id
+                            );
+                        }
+                    }
+                    self.set_status(TaskStatus::Done {
+                        duration,
+                        output: log,
+                    })
+                    .await;
+                    return Ok(());
+                }
+                IPCEvent::Failed(error) => {
+                    let log = proc.take_log().await;
+                    self.set_status(TaskStatus::Failed {
+                        error: format!("{error}\n\n{log}"),
+                    })
+                    .await;
                     return Ok(());
                 }
             }
             if self.request_cancel.load(Ordering::Relaxed) {
-                child.kill().await?;
-                *self.status.lock().await = TaskStatus::Canceled;
+                // The worker is in the middle of a job, so it has to go. A
+                // new one is spawned for the next task.
+                proc.kill_tree().await?;
+                *worker = None;
+                self.set_status(TaskStatus::Canceled).await;
                 return Ok(());
             }
         }
 
-        let output = child.wait_with_output().await?;
-        if !output.status.success() {
-            *self.status.lock().await = TaskStatus::Failed {
-                error: format!(
-                    "Child process exited abnormally ({:?})\n\n{}",
-                    output.status.code(),
-                    String::from_utf8(output.stderr)?
-                ),
-            };
-            return Ok(());
-        }
-
+        let status = proc.wait().await?;
+        let log = proc.take_log().await;
+        *worker = None;
+        self.set_status(TaskStatus::Failed {
+            error: format!(
+                "Child process exited abnormally ({:?})\n\n{log}",
+                status.code()
+            ),
+        })
+        .await;
         Ok(())
     }
 
@@ -196,10 +278,12 @@ impl Task {
     pub async fn to_view(&self) -> TaskView {
         TaskView {
             id: self.id,
+            batch: self.batch.as_ref().and_then(Weak::upgrade).map(|it| it.id),
             name: self.name.clone(),
             output: self.output.clone(),
             path: self.params.path.display().to_string(),
             cover: self.cover.path().display().to_string(),
+            mods: self.params.config.mods().to_vec(),
             status: self.status.lock().await.clone(),
         }
     }
@@ -208,53 +292,395 @@ impl Task {
 #[derive(Serialize)]
 pub struct TaskView {
     id: u32,
+    batch: Option<u32>,
     name: String,
     output: PathBuf,
     path: String,
     cover: String,
+    mods: Vec<Mod>,
     status: TaskStatus,
 }
 
+#[derive(Serialize)]
+#[serde(rename_all = "snake_case", tag = "type")]
+pub enum PostOutcome {
+    Queued {
+        id: u32,
+        /// Set when the output volume is running low on space.
+        warning: Option<String>,
+    },
+    /// An identical render already exists.
+    Cached { output: PathBuf },
+}
+
+#[derive(Serialize)]
+pub struct BatchOutcome {
+    id: u32,
+    /// Set when an output volume is running low on space.
+    warning: Option<String>,
+}
+
+/// Tasks posted together, e.g. every chart of a song pack.
+pub struct Batch {
+    id: u32,
+    name: String,
+    tasks: Mutex<Vec<Arc<Task>>>,
+    summarized: AtomicBool,
+}
+
+#[derive(Serialize, Clone)]
+pub struct BatchSummary {
+    id: u32,
+    name: String,
+    total: usize,
+    succeeded: usize,
+    failed: usize,
+    canceled: usize,
+    /// Sum of the render durations of the succeeded tasks.
+    duration: f64,
+}
+
+#[derive(Serialize)]
+pub struct BatchView {
+    #[serde(flatten)]
+    summary: BatchSummary,
+    tasks: Vec<u32>,
+    finished: bool,
+    progress: f64,
+    estimate: Option<f64>,
+}
+
+impl Batch {
+    async fn to_view(&self) -> BatchView {
+        let tasks = self.tasks.lock().await;
+        let mut summary = BatchSummary {
+            id: self.id,
+            name: self.name.clone(),
+            total: tasks.len(),
+            succeeded: 0,
+            failed: 0,
+            canceled: 0,
+            duration: 0.,
+        };
+        let mut progress = 0.;
+        let mut pending = 0;
+        let mut running_estimate = Some(0.);
+        for task in tasks.iter() {
+            match &*task.status.lock().await {
+                TaskStatus::Pending => pending += 1,
+                TaskStatus::Loading { progress: it }
+                | TaskStatus::Mixing { progress: it }
+                | TaskStatus::Rendering { progress: it, .. }
+                | TaskStatus::Finalizing { progress: it } => {
+                    progress += it.total;
+                    running_estimate = running_estimate.zip(it.estimate).map(|(a, b)| a + b);
+                }
+                TaskStatus::Done { duration, .. } => {
+                    summary.succeeded += 1;
+                    summary.duration += duration;
+                }
+                TaskStatus::Canceled => summary.canceled += 1,
+                TaskStatus::Failed { .. } => summary.failed += 1,
+            }
+        }
+        let finished = summary.succeeded + summary.failed + summary.canceled;
+        progress += finished as f64;
+        // Pending tasks are assumed to take as long as the average succeeded
+        // one.
+        let estimate = if pending == 0 {
+            running_estimate
+        } else if summary.succeeded > 0 {
+            let average = summary.duration / summary.succeeded as f64;
+            running_estimate.map(|it| it + average * pending as f64)
+        } else {
+            None
+        };
+        BatchView {
+            tasks: tasks.iter().map(|it| it.id).collect(),
+            finished: finished == summary.total,
+            progress: if summary.total == 0 {
+                1.
+            } else {
+                progress / summary.total as f64
+            },
+            estimate,
+            summary,
+        }
+    }
+
+    /// Emits a `batch-finished` event with the summary once every task of
+    /// the batch has finished.
+    async fn check_finished(&self, app: &AppHandle) {
+        let view = self.to_view().await;
+        if !view.finished || self.summarized.swap(true, Ordering::Relaxed) {
+            return;
+        }
+        info!(
+            "Batch #{} finished: {} succeeded, {} failed, {} canceled",
+            self.id, view.summary.succeeded, view.summary.failed, view.summary.canceled
+        );
+        if let Err(err) = app.emit_all("batch-finished", view.summary) {
+            warn!("Failed to emit summary of batch #{}: {err:?}", self.id);
+        }
+    }
+}
+
 pub struct TaskQueue {
+    app: AppHandle,
     sender: mpsc::UnboundedSender<Arc<Task>>,
     worker: JoinHandle<()>,
 
     tasks: Mutex<Vec<Arc<Task>>>,
+    next_id: AtomicU32,
+    batches: Mutex<Vec<Arc<Batch>>>,
+    next_batch_id: AtomicU32,
 }
 impl TaskQueue {
-    pub fn new() -> Self {
+    pub fn new(app: AppHandle) -> Self {
         let (sender, mut receiver) = mpsc::unbounded_channel::<Arc<Task>>();
-        let task = tokio::spawn(async move {
-            loop {
-                let Ok(task) = receiver.try_recv() else {
-                    std::thread::sleep(std::time::Duration::from_millis(200));
-                    continue
-                };
-                if let Err(err) = task.run().await {
-                    error!("Failed to render: {err:?}");
-                    *task.status.lock().await = TaskStatus::Failed {
-                        error: format!("{err:?}"),
+        let task = tokio::spawn({
+            let app = app.clone();
+            async move {
+                let mut worker = None;
+                loop {
+                    let Ok(task) = receiver.try_recv() else {
+                        std::thread::sleep(std::time::Duration::from_millis(200));
+                        continue
                     };
+                    if let Err(err) = task.run(&mut worker).await {
+                        error!("Failed to render: {err:?}");
+                        // The worker may be stuck in the middle of the job.
+                        worker = None;
+                        task.set_status(TaskStatus::Failed {
+                            error: format!("{err:?}"),
+                        })
+                        .await;
+                    }
+                    if let Some(batch) = task.batch.as_ref().and_then(Weak::upgrade) {
+                        batch.check_finished(&app).await;
+                    }
                 }
             }
         });
 
         Self {
+            app,
             sender,
             worker: task,
 
             tasks: Mutex::default(),
+            next_id: AtomicU32::default(),
+            batches: Mutex::default(),
+            next_batch_id: AtomicU32::default(),
         }
     }
 
-    pub async fn post(&self, params: RenderParams) -> Result<u32> {
-        let mut guard = self.tasks.lock().await;
-        let id = guard.len() as u32;
-        let task = Arc::new(Task::new(id, params).await?);
-        guard.push(Arc::clone(&task));
+    /// Creates a task without an output path. This loads the chart, so it's
+    /// done without holding the task list.
+    async fn create(&self, params: RenderParams, batch: Option<&Arc<Batch>>) -> Result<Task> {
+        params.config.validate()?;
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let mut task = Task::new(self.app.clone(), id, batch.map(Arc::downgrade), params).await?;
+        let params = task.params.clone();
+        match tokio::task::spawn_blocking(move || cache::cache_key(&params)).await? {
+            Ok(key) => task.cache_key = Some(key),
+            Err(err) => warn!("Failed to compute the cache key of task #{id}: {err:?}"),
+        }
+        Ok(task)
+    }
+
+    /// Picks an output path for `task` that doesn't clash with any of `taken`.
+    fn assign_output<'a>(
+        task: &mut Task,
+        taken: impl Iterator<Item = &'a Arc<Task>> + Clone,
+    ) -> Result<()> {
+        task.output = output::output_path(&task.params, |path| {
+            taken.clone().any(|it| it.output == path)
+        })?;
+        Ok(())
+    }
+
+    fn enqueue(&self, tasks: &mut Vec<Arc<Task>>, task: Arc<Task>) -> Result<()> {
+        task.emit_status(TaskStatus::Pending);
+        tasks.push(Arc::clone(&task));
         self.sender.send(task)?;
+        Ok(())
+    }
+
+    /// Queues a render. If an identical render was done before, nothing is
+    /// queued and [`PostOutcome::Cached`] is returned unless `reuse` says
+    /// whether to reuse it or to render again.
+    pub async fn post(&self, params: RenderParams, reuse: Option<bool>) -> Result<PostOutcome> {
+        let mut task = self.create(params, None).await?;
+        let id = task.id;
+        let cached = match (&task.cache_key, reuse) {
+            (Some(key), None | Some(true)) => {
+                let key = key.clone();
+                tokio::task::spawn_blocking(move || cache::lookup(&key)).await?
+            }
+            _ => None,
+        };
+        if let Some(entry) = cached {
+            if reuse.is_none() {
+                return Ok(PostOutcome::Cached {
+                    output: entry.output,
+                });
+            }
+            info!("Task #{id} reuses {}", entry.output.display());
+            task.output = entry.output;
+            let status = TaskStatus::Done {
+                duration: 0.,
+                output: "Reused an identical previous render".to_owned(),
+            };
+            *task.status.get_mut() = status.clone();
+            task.emit_status(status);
+            self.tasks.lock().await.push(Arc::new(task));
+            return Ok(PostOutcome::Queued { id, warning: None });
+        }
+        let estimate = preflight::estimate(&task.params).await?;
+        let mut guard = self.tasks.lock().await;
+        Self::assign_output(&mut task, guard.iter())?;
+        let warning = preflight::check([(task.output.as_path(), &estimate)])?;
+        self.enqueue(&mut guard, Arc::new(task))?;
+
+        Ok(PostOutcome::Queued { id, warning })
+    }
+
+    /// Posts several tasks as one batch. Nothing is queued if any of the
+    /// charts fails to load, or if there isn't enough space for all of them.
+    pub async fn post_batch(
+        &self,
+        name: String,
+        params: Vec<RenderParams>,
+    ) -> Result<BatchOutcome> {
+        let batch = Arc::new(Batch {
+            id: self.next_batch_id.fetch_add(1, Ordering::Relaxed),
+            name,
+            tasks: Mutex::default(),
+            summarized: AtomicBool::default(),
+        });
+        let mut created = Vec::with_capacity(params.len());
+        let mut estimates = Vec::with_capacity(params.len());
+        for params in params {
+            let path = params.path.display().to_string();
+            let task = self
+                .create(params, Some(&batch))
+                .await
+                .with_context(|| path.clone())?;
+            estimates.push(
+                preflight::estimate(&task.params)
+                    .await
+                    .with_context(|| path)?,
+            );
+            created.push(task);
+        }
+
+        let mut guard = self.tasks.lock().await;
+        let mut tasks: Vec<Arc<Task>> = Vec::with_capacity(created.len());
+        for mut task in created {
+            Self::assign_output(&mut task, guard.iter().chain(&tasks))?;
+            tasks.push(Arc::new(task));
+        }
+        let warning = preflight::check(tasks.iter().map(|it| it.output.as_path()).zip(&estimates))?;
+        *batch.tasks.lock().await = tasks.clone();
+        self.batches.lock().await.push(Arc::clone(&batch));
+        for task in tasks {
+            self.enqueue(&mut guard, task)?;
+        }
+
+        Ok(BatchOutcome {
+            id: batch.id,
+            warning,
+        })
+    }
+
+    async fn find_batch(&self, id: u32) -> Result<Arc<Batch>> {
+        self.batches
+            .lock()
+            .await
+            .iter()
+            .find(|it| it.id == id)
+            .cloned()
+            .ok_or_else(|| anyhow!(mtl!("batch-not-found")))
+    }
+
+    pub async fn batches(&self) -> Vec<BatchView> {
+        let guard = self.batches.lock().await;
+        let mut result = Vec::with_capacity(guard.len());
+        for batch in guard.iter() {
+            result.push(batch.to_view().await);
+        }
+        result.reverse();
+        result
+    }
 
-        Ok(id)
+    pub async fn cancel_batch(&self, id: u32) -> Result<()> {
+        let batch = self.find_batch(id).await?;
+        for task in batch.tasks.lock().await.iter() {
+            task.cancel();
+        }
+        Ok(())
+    }
+
+    /// Re-queues the failed and canceled tasks of a batch.
+    pub async fn retry_batch(&self, id: u32) -> Result<()> {
+        let batch = self.find_batch(id).await?;
+        let mut failed = Vec::new();
+        for task in batch.tasks.lock().await.iter() {
+            if matches!(
+                *task.status.lock().await,
+                TaskStatus::Failed { .. } | TaskStatus::Canceled
+            ) {
+                failed.push(Arc::clone(task));
+            }
+        }
+        let mut created = Vec::with_capacity(failed.len());
+        for old in failed {
+            let mut task = self.create(old.params.clone(), Some(&batch)).await?;
+            // Overwrite whatever the failed attempt left behind
+            task.output = old.output.clone();
+            created.push((old, Arc::new(task)));
+        }
+
+        let mut guard = self.tasks.lock().await;
+        let mut batch_tasks = batch.tasks.lock().await;
+        let mut retried = Vec::with_capacity(created.len());
+        for (old, task) in created {
+            // Skip tasks that were removed in the meantime
+            let Some(slot) = batch_tasks.iter_mut().find(|it| Arc::ptr_eq(it, &old)) else {
+                continue;
+            };
+            *slot = Arc::clone(&task);
+            guard.retain(|it| !Arc::ptr_eq(it, &old));
+            retried.push(task);
+        }
+        drop(batch_tasks);
+        if !retried.is_empty() {
+            batch.summarized.store(false, Ordering::Relaxed);
+        }
+        for task in retried {
+            self.enqueue(&mut guard, task)?;
+        }
+        Ok(())
+    }
+
+    /// Drops removed tasks from their batches, and batches left empty.
+    async fn detach(&self, removed: &[Arc<Task>]) {
+        let mut batches = self.batches.lock().await;
+        for batch in batches.iter() {
+            batch
+                .tasks
+                .lock()
+                .await
+                .retain(|it| !removed.iter().any(|removed| Arc::ptr_eq(it, removed)));
+        }
+        let mut kept = Vec::with_capacity(batches.len());
+        for batch in batches.drain(..) {
+            if !batch.tasks.lock().await.is_empty() {
+                kept.push(batch);
+            }
+        }
+        *batches = kept;
     }
 
     pub async fn tasks(&self) -> Vec<TaskView> {
@@ -267,8 +693,47 @@ impl TaskQueue {
         result
     }
 
-    pub async fn cancel(&self, id: u32) {
-        self.tasks.lock().await[id as usize].cancel();
+    pub async fn cancel(&self, id: u32) -> Result<()> {
+        let guard = self.tasks.lock().await;
+        let task = guard
+            .iter()
+            .find(|it| it.id == id)
+            .ok_or_else(|| anyhow!(mtl!("task-not-found")))?;
+        task.cancel();
+        Ok(())
+    }
+
+    /// Removes a finished task from the list, deleting its cover.
+    pub async fn remove(&self, id: u32) -> Result<()> {
+        let mut guard = self.tasks.lock().await;
+        let Some(index) = guard.iter().position(|it| it.id == id) else {
+            bail!(mtl!("task-not-found"));
+        };
+        if !guard[index].status.lock().await.is_finished() {
+            bail!(mtl!("task-not-finished"));
+        }
+        let task = guard.remove(index);
+        drop(guard);
+        self.detach(&[task]).await;
+        Ok(())
+    }
+
+    /// Removes every finished task, returning how many were removed.
+    pub async fn clear_finished(&self) -> usize {
+        let mut guard = self.tasks.lock().await;
+        let mut kept = Vec::with_capacity(guard.len());
+        let mut removed = Vec::new();
+        for task in guard.drain(..) {
+            if task.status.lock().await.is_finished() {
+                removed.push(task);
+            } else {
+                kept.push(task);
+            }
+        }
+        *guard = kept;
+        drop(guard);
+        self.detach(&removed).await;
+        removed.len()
     }
 }
 
@@ -0,0 +1,58 @@
+use serde::Serialize;
+use std::fmt;
+
+#[derive(Debug, Serialize)]
+pub struct FieldError {
+    /// camelCase path of the field, like `motionBlur.subFrames`.
+    pub field: String,
+    pub message: String,
+}
+
+/// A config that failed validation, with an error for every offending field.
+/// It's passed to the frontend as is, so that the errors can be shown next
+/// to the inputs.
+#[derive(Debug, Serialize)]
+pub struct InvalidConfig {
+    pub message: String,
+    pub fields: Vec<FieldError>,
+}
+
+impl fmt::Display for InvalidConfig {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        for error in &self.fields {
+            write!(f, "\n{}: {}", error.field, error.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for InvalidConfig {}
+
+#[derive(Default)]
+pub struct Validator {
+    fields: Vec<FieldError>,
+}
+
+impl Validator {
+    /// Records an error for `field` unless `ok` holds.
+    pub fn check(&mut self, field: &str, ok: bool, message: impl FnOnce() -> String) {
+        if !ok {
+            self.fields.push(FieldError {
+                field: field.to_owned(),
+                message: message(),
+            });
+        }
+    }
+
+    pub fn finish(self, message: impl FnOnce() -> String) -> Result<(), InvalidConfig> {
+        if self.fields.is_empty() {
+            Ok(())
+        } else {
+            Err(InvalidConfig {
+                message: message(),
+                fields: self.fields,
+            })
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use crate::{
+    fps::FrameRate,
+    render::{cmd_hidden, find_ffmpeg, find_ffprobe},
+};
+use anyhow::{bail, ensure, Context, Result};
+use serde::Deserialize;
+use std::path::Path;
+use tracing::warn;
+
+/// Allowed drift between the audio and video streams. Encoders pad audio to
+/// whole packets (1024 samples for AAC, 1152 for MP3), plus some priming.
+const AUDIO_TOLERANCE: f64 = 0.1;
+
+#[derive(Deserialize)]
+struct Probe {
+    #[serde(default)]
+    streams: Vec<ProbeStream>,
+    format: Option<ProbeFormat>,
+}
+
+#[derive(Deserialize)]
+struct ProbeStream {
+    codec_type: String,
+    nb_read_packets: Option<String>,
+    duration: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct ProbeFormat {
+    duration: Option<String>,
+}
+
+fn parse_duration(value: Option<&String>) -> Option<f64> {
+    value.and_then(|it| it.parse().ok())
+}
+
+/// Checks that a finished render is a complete mp4 holding `frames` video
+/// frames and an audio stream of the same length.
+///
+/// Without ffprobe, ffmpeg is used to check the streams and the frame count
+/// but not the durations. Without either, only the presence of the file can
+/// be checked, and a note saying so is returned.
+pub fn verify_output(path: &Path, frames: u64, fps: FrameRate) -> Result<Option<String>> {
+    let size = std::fs::metadata(path)
+        .with_context(|| format!("output file {} is missing", path.display()))?
+        .len();
+    ensure!(size > 0, "output file is empty");
+
+    let Some(ffprobe) = find_ffprobe()? else {
+        warn!("ffprobe not found, verifying with ffmpeg");
+        return verify_with_ffmpeg(path, frames);
+    };
+    let output = cmd_hidden(ffprobe)
+        .args(
+            "-v error -count_packets -show_entries stream=codec_type,nb_read_packets,duration:format=duration -of json"
+                .split_whitespace(),
+        )
+        .arg(path)
+        .output()
+        .context("failed to run ffprobe")?;
+    if !output.status.success() {
+        bail!(
+            "ffprobe could not read the output, it may be truncated: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let probe: Probe =
+        serde_json::from_slice(&output.stdout).context("failed to parse ffprobe output")?;
+
+    let find = |kind: &str| probe.streams.iter().find(|it| it.codec_type == kind);
+    let Some(video) = find("video") else { bail!("output has no video stream") };
+    let Some(audio) = find("audio") else { bail!("output has no audio stream") };
+
+    let packets: u64 = video
+        .nb_read_packets
+        .as_ref()
+        .and_then(|it| it.parse().ok())
+        .context("ffprobe did not report the video frame count")?;
+    ensure!(
+        packets == frames,
+        "output has {packets} video frames, expected {frames}"
+    );
+
+    let expected = fps.frame_time(frames);
+    let format_duration = probe
+        .format
+        .as_ref()
+        .and_then(|it| parse_duration(it.duration.as_ref()));
+    let video_duration = parse_duration(video.duration.as_ref())
+        .or(format_duration)
+        .context("ffprobe did not report the video duration")?;
+    ensure!(
+        (video_duration - expected).abs() <= fps.frame_delta(),
+        "video lasts {video_duration:.3}s, expected {expected:.3}s"
+    );
+
+    if let Some(audio_duration) = parse_duration(audio.duration.as_ref()) {
+        ensure!(
+            (audio_duration - video_duration).abs() <= AUDIO_TOLERANCE,
+            "audio ({audio_duration:.3}s) is out of sync with video ({video_duration:.3}s)"
+        );
+    }
+    Ok(None)
+}
+
+/// Remuxes the output into nothing, which reads every packet and fails if
+/// either stream is missing.
+fn verify_with_ffmpeg(path: &Path, frames: u64) -> Result<Option<String>> {
+    let Some(ffmpeg) = find_ffmpeg()? else {
+        warn!("Neither ffprobe nor ffmpeg found, skipping stream verification");
+        return Ok(Some(
+            "Stream verification skipped: neither ffprobe nor ffmpeg was found".to_owned(),
+        ));
+    };
+    let output = cmd_hidden(ffmpeg)
+        .args(["-v", "error", "-nostats", "-i"])
+        .arg(path)
+        .args("-map 0:v:0 -map 0:a:0 -c copy -f null -progress pipe:1 -".split_whitespace())
+        .output()
+        .context("failed to run ffmpeg")?;
+    if !output.status.success() {
+        bail!(
+            "ffmpeg could not read the output, it may be truncated: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let packets: u64 = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .filter_map(|it| it.strip_prefix("frame="))
+        .last()
+        .and_then(|it| it.trim().parse().ok())
+        .context("ffmpeg did not report the video frame count")?;
+    ensure!(
+        packets == frames,
+        "output has {packets} video frames, expected {frames}"
+    );
+    Ok(None)
+}
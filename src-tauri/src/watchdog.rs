@@ -0,0 +1,84 @@
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+/// Longest time in seconds to wait for the next event from the render worker
+/// in each phase of a job before treating it as hung.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase", default)]
+pub struct WatchdogConfig {
+    /// Starting the worker and loading the chart.
+    pub loading: f64,
+    /// Mixing and encoding the audio.
+    pub mixing: f64,
+    /// Between two rendered frames.
+    pub frame: f64,
+    /// Flushing the encoder after the last frame.
+    pub finalizing: f64,
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        Self {
+            loading: 300.,
+            mixing: 300.,
+            frame: 60.,
+            finalizing: 600.,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Phase {
+    Loading,
+    Mixing,
+    Rendering,
+    Finalizing,
+}
+
+impl Phase {
+    pub const ALL: [Phase; 4] = [
+        Self::Loading,
+        Self::Mixing,
+        Self::Rendering,
+        Self::Finalizing,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Loading => "loading",
+            Self::Mixing => "mixing",
+            Self::Rendering => "rendering",
+            Self::Finalizing => "finalizing",
+        }
+    }
+
+    /// Name of the [`WatchdogConfig`] field for the phase.
+    pub fn field(self) -> &'static str {
+        match self {
+            Self::Rendering => "frame",
+            _ => self.name(),
+        }
+    }
+}
+
+impl WatchdogConfig {
+    pub fn seconds(&self, phase: Phase) -> f64 {
+        match phase {
+            Phase::Loading => self.loading,
+            Phase::Mixing => self.mixing,
+            Phase::Rendering => self.frame,
+            Phase::Finalizing => self.finalizing,
+        }
+    }
+
+    pub fn timeout(&self, phase: Phase) -> Duration {
+        let secs = self.seconds(phase);
+        // Non-positive values disable the watchdog for that phase, and so do
+        // ones too large for a `Duration`.
+        if secs > 0. {
+            Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+        } else {
+            Duration::MAX
+        }
+    }
+}
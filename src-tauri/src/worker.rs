@@ -0,0 +1,133 @@
+use crate::{
+    common::DATA_DIR,
+    render::{cmd_hidden, IPCEvent, RenderParams},
+    ASSET_PATH,
+};
+use anyhow::Result;
+use std::{
+    path::Path,
+    process::{ExitStatus, Stdio},
+    sync::Arc,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader, Lines},
+    process::{Child, ChildStdin, ChildStdout},
+    sync::Mutex,
+    task::JoinHandle,
+};
+use tracing::warn;
+
+/// A long-lived `render` subprocess that renders jobs one after another, so
+/// that the GL context, shared assets and ffmpeg probing are only set up once.
+pub struct Worker {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Lines<BufReader<ChildStdout>>,
+    log: Arc<Mutex<String>>,
+    drain: Option<JoinHandle<()>>,
+}
+
+impl Worker {
+    pub fn spawn() -> Result<Self> {
+        let mut command = tokio::process::Command::new(std::env::current_exe()?);
+        command
+            .arg("render")
+            .arg(ASSET_PATH.get().unwrap())
+            // Caches such as mixed audio go here
+            .arg(DATA_DIR.get().unwrap())
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true);
+        // Own process group, so that ffmpeg (and Xvfb when headless) can be
+        // killed along with the worker.
+        #[cfg(unix)]
+        command.process_group(0);
+        let mut child = command.spawn()?;
+        let stdin = child.stdin.take().unwrap();
+        let lines = BufReader::new(child.stdout.take().unwrap()).lines();
+
+        // stderr has to be drained continuously, otherwise the worker blocks
+        // once the pipe is full.
+        let log = Arc::new(Mutex::new(String::new()));
+        let mut stderr = BufReader::new(child.stderr.take().unwrap()).lines();
+        let drain = tokio::spawn({
+            let log = Arc::clone(&log);
+            async move {
+                while let Ok(Some(line)) = stderr.next_line().await {
+                    let mut log = log.lock().await;
+                    log.push_str(&line);
+                    log.push('\n');
+                }
+            }
+        });
+
+        Ok(Self {
+            child,
+            stdin,
+            lines,
+            log,
+            drain: Some(drain),
+        })
+    }
+
+    /// Whether the worker can still accept jobs.
+    pub fn is_alive(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(None))
+    }
+
+    pub async fn submit(&mut self, params: &RenderParams, output: &Path) -> Result<()> {
+        self.log.lock().await.clear();
+        self.stdin
+            .write_all(format!("{}\n", serde_json::to_string(params)?).as_bytes())
+            .await?;
+        self.stdin
+            .write_all(format!("{}\n", serde_json::to_string(output)?).as_bytes())
+            .await?;
+        self.stdin.flush().await?;
+        Ok(())
+    }
+
+    /// Returns the next event of the current job, or `None` if the worker
+    /// exited.
+    pub async fn next_event(&mut self) -> Result<Option<IPCEvent>> {
+        while let Some(line) = self.lines.next_line().await? {
+            if let Ok(event) = serde_json::from_str(line.trim()) {
+                return Ok(Some(event));
+            }
+        }
+        Ok(None)
+    }
+
+    /// Takes the stderr output of the current job.
+    pub async fn take_log(&self) -> String {
+        std::mem::take(&mut *self.log.lock().await)
+    }
+
+    /// Kills the worker together with every process it spawned.
+    pub async fn kill_tree(&mut self) -> Result<()> {
+        if let Some(pid) = self.child.id() {
+            #[cfg(unix)]
+            let result = cmd_hidden("kill")
+                .args(["-KILL", "--", &format!("-{pid}")])
+                .output();
+            #[cfg(windows)]
+            let result = cmd_hidden("taskkill")
+                .args(["/T", "/F", "/PID", &pid.to_string()])
+                .output();
+            if let Err(err) = result {
+                warn!("Failed to kill the process tree of worker {pid}: {err:?}");
+            }
+        }
+        self.child.kill().await?;
+        Ok(())
+    }
+
+    pub async fn wait(&mut self) -> Result<ExitStatus> {
+        let status = self.child.wait().await?;
+        if let Some(drain) = self.drain.take() {
+            let _ = drain.await;
+        }
+        Ok(status)
+    }
+}
@@ -0,0 +1,89 @@
+use crate::encoder::FrameSink;
+use anyhow::{anyhow, Result};
+use std::{
+    sync::mpsc::{self, Receiver, SyncSender},
+    thread::JoinHandle,
+    time::{Duration, Instant},
+};
+
+/// Feeds frames to the encoder on a dedicated thread so that rendering and
+/// encoding overlap.
+///
+/// At most `capacity` frames are queued; once the queue is full,
+/// [`FrameWriter::write`] blocks and the time spent waiting is accounted as
+/// backpressure. Frame buffers are sent back after being written and reused.
+pub struct FrameWriter {
+    sender: Option<SyncSender<Vec<u8>>>,
+    recycle: Receiver<Vec<u8>>,
+    handle: Option<JoinHandle<Result<Duration>>>,
+    backpressure: Duration,
+}
+
+impl FrameWriter {
+    pub fn new(mut sink: Box<dyn FrameSink>, capacity: usize) -> Self {
+        let (sender, receiver) = mpsc::sync_channel::<Vec<u8>>(capacity);
+        let (recycle_sender, recycle) = mpsc::sync_channel(capacity + 2);
+        let handle = std::thread::spawn(move || {
+            let mut busy = Duration::ZERO;
+            for frame in receiver {
+                let start = Instant::now();
+                sink.write_frame(&frame)?;
+                busy += start.elapsed();
+                let _ = recycle_sender.try_send(frame);
+            }
+            let start = Instant::now();
+            sink.finish()?;
+            Ok(busy + start.elapsed())
+        });
+        Self {
+            sender: Some(sender),
+            recycle,
+            handle: Some(handle),
+            backpressure: Duration::ZERO,
+        }
+    }
+
+    pub fn write(&mut self, data: &[u8]) -> Result<()> {
+        let mut frame = self.recycle.try_recv().unwrap_or_default();
+        frame.clear();
+        frame.extend_from_slice(data);
+        let start = Instant::now();
+        let sent = self.sender.as_ref().unwrap().send(frame);
+        self.backpressure += start.elapsed();
+        if sent.is_err() {
+            // The writer thread has quit; surface its error.
+            return Err(self
+                .join()
+                .err()
+                .unwrap_or_else(|| anyhow!("frame writer exited")));
+        }
+        Ok(())
+    }
+
+    pub fn backpressure(&self) -> Duration {
+        self.backpressure
+    }
+
+    /// Waits for every queued frame to be written, finishes the sink and
+    /// returns the time the writer thread spent in it.
+    pub fn finish(mut self) -> Result<Duration> {
+        self.join()
+    }
+
+    fn join(&mut self) -> Result<Duration> {
+        drop(self.sender.take());
+        self.handle
+            .take()
+            .ok_or_else(|| anyhow!("frame writer already finished"))?
+            .join()
+            .map_err(|_| anyhow!("frame writer panicked"))?
+    }
+}
+
+impl Drop for FrameWriter {
+    fn drop(&mut self) {
+        if self.handle.is_some() {
+            let _ = self.join();
+        }
+    }
+}
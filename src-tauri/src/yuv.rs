@@ -0,0 +1,112 @@
+use crate::aa::{draw_fullscreen, VERTEX};
+use anyhow::{anyhow, Result};
+use macroquad::prelude::*;
+
+// Packs a vertically flipped BT.709 limited range YUV420P image into an
+// RGBA target of (w / 4) x (h * 3 / 2), so that the raw readback is exactly
+// the planar layout ffmpeg expects: every texel holds four consecutive bytes
+// of the Y, U or V plane.
+const FRAGMENT: &str = r#"#version 100
+precision highp float;
+
+uniform sampler2D Texture;
+uniform vec2 size;
+
+vec3 rgb(vec2 px) {
+    return texture2D(Texture, vec2(px.x / size.x, 1.0 - px.y / size.y)).rgb;
+}
+
+float luma(vec2 px) {
+    return (16.0 + 219.0 * dot(rgb(px), vec3(0.2126, 0.7152, 0.0722))) / 255.0;
+}
+
+float chroma(float index, vec3 coeff) {
+    float width = size.x / 2.0;
+    float cy = floor(index / width);
+    float cx = index - cy * width;
+    // Sampling at the corner shared by a 2x2 block averages it.
+    vec3 c = rgb(vec2(cx * 2.0 + 1.0, cy * 2.0 + 1.0));
+    return (128.0 + 224.0 * dot(c, coeff)) / 255.0;
+}
+
+void main() {
+    float x = floor(gl_FragCoord.x) * 4.0;
+    float y = floor(gl_FragCoord.y);
+    if (y < size.y) {
+        gl_FragColor = vec4(
+            luma(vec2(x + 0.5, y + 0.5)),
+            luma(vec2(x + 1.5, y + 0.5)),
+            luma(vec2(x + 2.5, y + 0.5)),
+            luma(vec2(x + 3.5, y + 0.5))
+        );
+        return;
+    }
+    float row = y - size.y;
+    float quarter = size.y / 4.0;
+    vec3 coeff = vec3(-0.1146, -0.3854, 0.5);
+    if (row >= quarter) {
+        row -= quarter;
+        coeff = vec3(0.5, -0.4542, -0.0458);
+    }
+    float index = row * size.x + x;
+    gl_FragColor = vec4(
+        chroma(index, coeff),
+        chroma(index + 1.0, coeff),
+        chroma(index + 2.0, coeff),
+        chroma(index + 3.0, coeff)
+    );
+}
+"#;
+
+/// Converts rendered frames to YUV420P on the GPU, cutting the readback to
+/// 1.5 bytes per pixel.
+pub struct YuvConverter {
+    target: RenderTarget,
+    material: Material,
+    packed_dim: (u32, u32),
+}
+
+impl YuvConverter {
+    /// Chroma rows are packed two per texel row, so the width must be a
+    /// multiple of 8 and the height a multiple of 4.
+    pub fn supports(dim: (u32, u32)) -> bool {
+        dim.0 % 8 == 0 && dim.1 % 4 == 0
+    }
+
+    pub fn new(dim: (u32, u32)) -> Result<Self> {
+        let packed_dim = (dim.0 / 4, dim.1 * 3 / 2);
+        let target = render_target(packed_dim.0, packed_dim.1);
+        target.texture.set_filter(FilterMode::Nearest);
+        let material = load_material(
+            VERTEX,
+            FRAGMENT,
+            MaterialParams {
+                uniforms: vec![("size".to_owned(), UniformType::Float2)],
+                ..Default::default()
+            },
+        )
+        .map_err(|err| anyhow!("failed to load YUV shader: {err:?}"))?;
+        material.set_uniform("size", vec2(dim.0 as f32, dim.1 as f32));
+        Ok(Self {
+            target,
+            material,
+            packed_dim,
+        })
+    }
+
+    pub fn packed_dim(&self) -> (u32, u32) {
+        self.packed_dim
+    }
+
+    pub fn convert(&self, source: RenderTarget) -> RenderTarget {
+        source.texture.set_filter(FilterMode::Linear);
+        draw_fullscreen(source.texture, self.target, self.packed_dim, self.material);
+        self.target
+    }
+}
+
+impl Drop for YuvConverter {
+    fn drop(&mut self) {
+        self.target.delete();
+    }
+}
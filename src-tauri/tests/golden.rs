@@ -0,0 +1,377 @@
+//! Golden-frame regression tests for the renderer.
+//!
+//! A small fixture chart is rendered through the `render` subcommand at low
+//! resolution and frame rate, then selected frames are compared against the
+//! images in `tests/golden` and the mixed audio is checked for its length and
+//! hit sound positions.
+//!
+//! These tests need a GL context (software rendering is fine, see the
+//! headless section of the README), ffmpeg on `PATH` and a complete asset
+//! folder, so they are ignored by default:
+//!
+//! ```bash
+//! cargo test --test golden -- --ignored
+//! ```
+//!
+//! Set `PHIRA_RENDER_ASSETS` to use another asset folder, and
+//! `PHIRA_RENDER_UPDATE_GOLDEN=1` to (re)write the golden images after an
+//! intended visual change.
+//!
+//! The golden images are rendered with Mesa's llvmpipe through the headless
+//! path, as in the `golden` CI workflow, whose `update` input regenerates
+//! them as an artifact. Hardware drivers rasterize slightly differently and
+//! may exceed the tolerance.
+
+use prpr::scene::{GameScene, LoadingScene};
+use serde_json::json;
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::OnceLock,
+};
+use tempfile::TempDir;
+
+const O: f64 = LoadingScene::TOTAL_TIME as f64 + GameScene::BEFORE_TIME as f64;
+// Mirrors the result screen delay in `render::main`.
+const A: f64 = 0.7 + 0.3 + 0.4;
+
+const FPS: u32 = 10;
+const SAMPLE_RATE: u32 = 44100;
+const MUSIC_LENGTH: f64 = 3.;
+const ENDING_LENGTH: f64 = 1.;
+/// Times of the notes in `fixtures/golden/chart.json`.
+const NOTES: [f64; 3] = [1., 1.5, 2.];
+
+/// Largest accepted mean difference of block luminance, out of 255.
+const FRAME_TOLERANCE: f64 = 4.;
+/// Encoder delay and padding make audio timings slightly inexact.
+const AUDIO_TOLERANCE: f64 = 0.03;
+
+fn manifest_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+struct Rendered {
+    _dir: TempDir,
+    output: PathBuf,
+}
+
+fn write_silence(path: &Path, seconds: f64) {
+    let frames = (seconds * SAMPLE_RATE as f64) as u32;
+    let data_len = frames * 4;
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&2u16.to_le_bytes());
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * 4).to_le_bytes());
+    wav.extend_from_slice(&4u16.to_le_bytes());
+    wav.extend_from_slice(&16u16.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    wav.resize(44 + data_len as usize, 0);
+    std::fs::write(path, wav).unwrap();
+}
+
+fn render() -> &'static Rendered {
+    static RENDERED: OnceLock<Rendered> = OnceLock::new();
+    RENDERED.get_or_init(|| {
+        let assets = std::env::var_os("PHIRA_RENDER_ASSETS")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| manifest_dir().join("assets"));
+        let dir = tempfile::tempdir().unwrap();
+        let chart = dir.path().join("chart");
+        std::fs::create_dir(&chart).unwrap();
+        for file in ["info.yml", "chart.json"] {
+            std::fs::copy(
+                manifest_dir().join("tests/fixtures/golden").join(file),
+                chart.join(file),
+            )
+            .unwrap();
+        }
+        std::fs::copy(assets.join("player.jpg"), chart.join("illustration.jpg")).unwrap();
+        write_silence(&chart.join("music.wav"), MUSIC_LENGTH);
+
+        let params = json!({
+            "path": chart,
+            "info": {
+                "name": "Golden",
+                "level": "GD Lv.1",
+                "charter": "phira-render",
+                "composer": "phira-render",
+                "illustrator": "phira-render",
+                "chart": "chart.json",
+                "music": "music.wav",
+                "illustration": "illustration.jpg",
+                "tip": "phira-render",
+            },
+            "config": {
+                "resolution": [320, 180],
+                "endingLength": ENDING_LENGTH,
+                "fps": FPS,
+                "hardwareAccel": false,
+                "bitrate": "2M",
+                "antiAliasing": "msaa",
+                "motionBlur": null,
+                "gpuYuv": false,
+
+                "aggressive": false,
+                "challengeColor": "golden",
+                "challengeRank": 45,
+                "disableEffect": false,
+                "doubleHint": true,
+                "noteScale": 1.0,
+                // particles are random
+                "particle": false,
+                "playerAvatar": null,
+                "playerName": "Golden",
+                "playerRks": 15.0,
+                "sampleCount": 1,
+                "resPackPath": null,
+                "speed": 1.0,
+                "volumeMusic": 1.0,
+                "volumeSfx": 1.0,
+            },
+        });
+        let output = dir.path().join("output.mp4");
+
+        let mut child = Command::new(env!("CARGO_BIN_EXE_phira-render"))
+            .arg("render")
+            .arg(&assets)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut stdin = child.stdin.take().unwrap();
+        writeln!(stdin, "{}", params).unwrap();
+        writeln!(stdin, "{}", serde_json::to_string(&output).unwrap()).unwrap();
+        drop(stdin);
+        let result = child.wait_with_output().unwrap();
+        assert!(result.status.success(), "render failed ({})", result.status);
+        // The worker reports failed jobs over IPC and keeps running.
+        let events = String::from_utf8_lossy(&result.stdout);
+        assert!(
+            events.lines().any(|it| it.starts_with(r#"{"Done""#)),
+            "render failed:\n{events}"
+        );
+
+        Rendered { _dir: dir, output }
+    })
+}
+
+fn ffmpeg(args: &[&str]) -> Vec<u8> {
+    let output = Command::new("ffmpeg")
+        .args(["-v", "error"])
+        .args(args)
+        .stderr(Stdio::inherit())
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "ffmpeg {args:?} failed");
+    output.stdout
+}
+
+struct Image {
+    width: usize,
+    height: usize,
+    rgb: Vec<u8>,
+}
+
+impl Image {
+    fn parse_ppm(data: &[u8]) -> Image {
+        let mut fields = Vec::new();
+        let mut pos = 0;
+        while fields.len() < 4 {
+            while data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            if data[pos] == b'#' {
+                while data[pos] != b'\n' {
+                    pos += 1;
+                }
+                continue;
+            }
+            let start = pos;
+            while !data[pos].is_ascii_whitespace() {
+                pos += 1;
+            }
+            fields.push(std::str::from_utf8(&data[start..pos]).unwrap().to_owned());
+        }
+        assert_eq!(fields[0], "P6", "not a binary PPM");
+        assert_eq!(fields[3], "255");
+        let width: usize = fields[1].parse().unwrap();
+        let height: usize = fields[2].parse().unwrap();
+        let rgb = data[pos + 1..pos + 1 + width * height * 3].to_vec();
+        Image { width, height, rgb }
+    }
+
+    fn to_ppm(&self) -> Vec<u8> {
+        let mut data = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        data.extend_from_slice(&self.rgb);
+        data
+    }
+
+    /// Mean luminance of 8x8 blocks, which hides encoding noise while keeping
+    /// anything that visibly moved or changed color.
+    fn blocks(&self) -> Vec<f64> {
+        let mut result = Vec::new();
+        for by in (0..self.height).step_by(8) {
+            for bx in (0..self.width).step_by(8) {
+                let mut sum = 0.;
+                let mut count = 0;
+                for y in by..(by + 8).min(self.height) {
+                    for x in bx..(bx + 8).min(self.width) {
+                        let p = &self.rgb[(y * self.width + x) * 3..][..3];
+                        sum += 0.2126 * p[0] as f64 + 0.7152 * p[1] as f64 + 0.0722 * p[2] as f64;
+                        count += 1;
+                    }
+                }
+                result.push(sum / count as f64);
+            }
+        }
+        result
+    }
+
+    fn diff(&self, other: &Image) -> f64 {
+        assert_eq!((self.width, self.height), (other.width, other.height));
+        let (a, b) = (self.blocks(), other.blocks());
+        a.iter().zip(&b).map(|(x, y)| (x - y).abs()).sum::<f64>() / a.len() as f64
+    }
+}
+
+fn check_frame(name: &str, time: f64) {
+    let rendered = render();
+    let frame = Image::parse_ppm(&ffmpeg(&[
+        "-ss",
+        &format!("{time:.3}"),
+        "-i",
+        rendered.output.to_str().unwrap(),
+        "-frames:v",
+        "1",
+        "-f",
+        "image2pipe",
+        "-c:v",
+        "ppm",
+        "-",
+    ]));
+    assert_eq!((frame.width, frame.height), (320, 180));
+
+    let golden = manifest_dir()
+        .join("tests/golden")
+        .join(format!("{name}.ppm"));
+    if std::env::var_os("PHIRA_RENDER_UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(golden.parent().unwrap()).unwrap();
+        std::fs::write(&golden, frame.to_ppm()).unwrap();
+        return;
+    }
+    let expected = std::fs::read(&golden).unwrap_or_else(|_| {
+        panic!(
+            "missing golden image {}, run with PHIRA_RENDER_UPDATE_GOLDEN=1 to create it",
+            golden.display()
+        )
+    });
+    let diff = frame.diff(&Image::parse_ppm(&expected));
+    if diff > FRAME_TOLERANCE {
+        let actual = std::env::temp_dir().join(format!("phira-render-{name}.ppm"));
+        std::fs::write(&actual, frame.to_ppm()).unwrap();
+        panic!(
+            "frame `{name}` differs from golden image by {diff:.2} (tolerance {FRAME_TOLERANCE}), actual frame saved to {}",
+            actual.display()
+        );
+    }
+}
+
+fn audio() -> Vec<f32> {
+    let data = ffmpeg(&[
+        "-i",
+        render().output.to_str().unwrap(),
+        "-vn",
+        "-ac",
+        "1",
+        "-ar",
+        &SAMPLE_RATE.to_string(),
+        "-f",
+        "f32le",
+        "-",
+    ]);
+    data.chunks_exact(4)
+        .map(|it| f32::from_le_bytes(it.try_into().unwrap()))
+        .collect()
+}
+
+fn video_length() -> f64 {
+    let video_length = O + (MUSIC_LENGTH + 1.) + A + ENDING_LENGTH;
+    (video_length * FPS as f64).ceil() / FPS as f64
+}
+
+#[test]
+#[ignore = "needs a GL context, ffmpeg and the full asset folder"]
+fn golden_loading() {
+    check_frame("loading", 1.);
+}
+
+#[test]
+#[ignore = "needs a GL context, ffmpeg and the full asset folder"]
+fn golden_gameplay() {
+    check_frame("gameplay-before-hit", O + NOTES[0] - 0.3);
+    check_frame("gameplay-after-hit", O + NOTES[0] + 0.1);
+}
+
+#[test]
+#[ignore = "needs a GL context, ffmpeg and the full asset folder"]
+fn golden_result() {
+    check_frame("result", video_length() - 0.2);
+}
+
+#[test]
+#[ignore = "needs a GL context, ffmpeg and the full asset folder"]
+fn audio_length() {
+    let length = audio().len() as f64 / SAMPLE_RATE as f64;
+    let expected = video_length();
+    assert!(
+        (length - expected).abs() < AUDIO_TOLERANCE * 2.,
+        "audio is {length:.3}s long, expected {expected:.3}s"
+    );
+}
+
+#[test]
+#[ignore = "needs a GL context, ffmpeg and the full asset folder"]
+fn sfx_positions() {
+    // The fixture music is silent, so every onset before the result screen
+    // music starts must be a hit sound.
+    const WINDOW: usize = SAMPLE_RATE as usize / 200;
+    let samples = audio();
+    let ending = O + MUSIC_LENGTH + 1. + A;
+    let mut onsets = Vec::new();
+    let mut quiet = true;
+    for (index, window) in samples.chunks(WINDOW).enumerate() {
+        let time = (index * WINDOW) as f64 / SAMPLE_RATE as f64;
+        if time >= ending - AUDIO_TOLERANCE {
+            break;
+        }
+        let rms = (window.iter().map(|it| it * it).sum::<f32>() / window.len() as f32).sqrt();
+        if quiet && rms > 0.01 {
+            // refine to the first loud sample in the window
+            let offset = window.iter().position(|it| it.abs() > 0.01).unwrap_or(0);
+            onsets.push((index * WINDOW + offset) as f64 / SAMPLE_RATE as f64);
+            quiet = false;
+        } else if rms < 0.001 {
+            quiet = true;
+        }
+    }
+    let expected: Vec<f64> = NOTES.iter().map(|it| O + it).collect();
+    assert_eq!(
+        onsets.len(),
+        expected.len(),
+        "found hit sounds at {onsets:?}, expected {expected:?}"
+    );
+    for (onset, expected) in onsets.iter().zip(&expected) {
+        assert!(
+            (onset - expected).abs() < AUDIO_TOLERANCE,
+            "hit sound at {onset:.3}s, expected {expected:.3}s"
+        );
+    }
+}
@@ -20,7 +20,9 @@ export function isString(s: unknown): s is string {
 
 export const RULES = {
   non_empty: (value: string) => value.trim().length > 0 || i18n.global.t('rules.non-empty'),
+  number: (value: string) => isNumeric(value) || i18n.global.t('rules.number'),
   positive: (value: string) => (isNumeric(value) && Number(value) > 0) || i18n.global.t('rules.positive'),
+  nonNegative: (value: string) => (isNumeric(value) && Number(value) >= 0) || i18n.global.t('rules.non-negative'),
   positiveInt: (value: string) => (isNumeric(value) && Math.abs(Number(value) - Math.round(Number(value))) < 1e-4 && Number(value) > 0) || i18n.global.t('rules.positive-int'),
 };
 
@@ -59,6 +61,7 @@ export function toast(message: string, kind?: 'success' | 'info' | 'warning' | '
 
 export function toastError(error: any) {
   console.error(error);
-  const msg = error instanceof Error ? error.message : String(error);
+  let msg = String(error);
+  if (error instanceof Error || (typeof error === 'object' && error && 'message' in error)) msg = String(error.message);
   if (msg.length) toast(msg, 'error');
 }
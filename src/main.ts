@@ -38,10 +38,13 @@ const i18n = createI18n({
     en: {
       rules: {
         'non-empty': 'Must not be empty',
+        number: 'Must be a number',
         positive: 'Must be a positive number',
+        'non-negative': 'Must not be negative',
         'positive-int': 'Must be a positive integer',
         resolution: "Must be like '1920x1080'",
         'sample-count': 'Must be a power of 2',
+        fps: "Must be like '60', '59.94' or '30000/1001'",
       },
       'has-error': 'There are errors in the configuration',
       'any-filter': 'All files',
@@ -49,10 +52,13 @@ const i18n = createI18n({
     'zh-CN': {
       rules: {
         'non-empty': '不能为空',
+        number: '必须是数字',
         positive: '必须是正数',
+        'non-negative': '不能为负数',
         'positive-int': '必须是正整数',
         resolution: "必须类似 '1920x1080'",
         'sample-count': '必须是 2 的幂',
+        fps: "必须类似 '60'、'59.94' 或 '30000/1001'",
       },
       'has-error': '配置中有错误',
       'any-filter': '所有文件',
@@ -11,21 +11,32 @@ export interface ChartInfo {
   backgroundDim: number;
 }
 
+export interface Progress {
+  phase: number | null;
+  total: number;
+  estimate: number | null;
+}
+
 export type TaskStatus =
   | {
       type: 'pending';
     }
   | {
       type: 'loading';
+      progress: Progress;
     }
   | {
       type: 'mixing';
+      progress: Progress;
     }
   | {
       type: 'rendering';
-      progress: number;
+      progress: Progress;
       fps: number;
-      estimate: number;
+    }
+  | {
+      type: 'finalizing';
+      progress: Progress;
     }
   | {
       type: 'done';
@@ -42,26 +53,97 @@ export type TaskStatus =
 
 export interface Task {
   id: number;
+  batch: number | null;
   name: string;
   output: string;
   path: string;
   cover: string;
+  mods: Mod[];
   status: TaskStatus;
 }
 
+export interface BatchOutcome {
+  id: number;
+  warning: string | null;
+}
+
+export type PostOutcome =
+  | {
+      type: 'queued';
+      id: number;
+      warning: string | null;
+    }
+  | {
+      type: 'cached';
+      output: string;
+    };
+
+export interface BatchSummary {
+  id: number;
+  name: string;
+  total: number;
+  succeeded: number;
+  failed: number;
+  canceled: number;
+  duration: number;
+}
+
+export interface Batch extends BatchSummary {
+  tasks: number[];
+  finished: boolean;
+  progress: number;
+  estimate: number | null;
+}
+
+export type AntiAliasing = 'none' | 'msaa' | 'fxaa' | 'ssaa2x' | 'ssaa4x';
+
+export interface MotionBlur {
+  subFrames: number;
+  shutterAngle: number;
+}
+
+export interface Simulation {
+  seed: number;
+  perfect: number;
+  good: number;
+  bad: number;
+  miss: number;
+  spread: number;
+}
+
+export interface Watchdog {
+  loading: number;
+  mixing: number;
+  frame: number;
+  finalizing: number;
+}
+
+export interface OutputConfig {
+  dir: string | null;
+  filename: string;
+}
+
+export type Mod = 'flipX' | 'fadeIn' | 'fadeOut';
+
 export interface RenderConfig {
   resolution: number[];
   endingLength: number;
-  fps: number;
+  fps: string;
   hardwareAccel: boolean;
   bitrate: string;
+  antiAliasing: AntiAliasing;
+  motionBlur: MotionBlur | null;
+  gpuYuv: boolean;
+  watchdog: Watchdog;
+  output: OutputConfig;
+  mods: Mod[];
+  simulation: Simulation | null;
 
   aggressive: boolean;
   challengeColor: string;
   challengeRank: number;
   disableEffect: boolean;
   doubleHint: boolean;
-  fxaa: boolean;
   noteScale: number;
   particle: boolean;
   playerAvatar: string | null;
@@ -72,6 +154,15 @@ export interface RenderConfig {
   speed: number;
   volumeMusic: number;
   volumeSfx: number;
+  offset: number;
+  chartDebug: boolean;
+
+  game: Record<string, unknown>;
+}
+
+export interface InvalidConfig {
+  message: string;
+  fields: { field: string; message: string }[];
 }
 
 export interface RPEChart {